@@ -0,0 +1,102 @@
+use near_sdk::json_types::{Base58CryptoHash, U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::{env, near, AccountId};
+
+use crate::AssetWeight;
+
+// Defaults for the NEP-297 `standard` and `version`; forks can set their own at `new`
+pub const EVENT_STANDARD: &str = "index_fund";
+pub const EVENT_VERSION: &str = "1.0.0";
+
+// NEP-297 events emitted by the index fund. The standard and version are contract settings
+// rather than compile-time attributes, so they're supplied when emitting.
+#[near(serializers = [json])]
+#[serde(tag = "event", content = "data", rename_all = "snake_case")]
+pub enum IndexFundEvent {
+    Paused {
+        by: AccountId,
+    },
+
+    Unpaused {
+        by: AccountId,
+    },
+
+    LastRebalanceSet {
+        old: U64,
+        new: U64,
+    },
+
+    AssetAdded {
+        asset_address: AccountId,
+        initial_weight: U64,
+    },
+
+    EmergencyWeightsSet {
+        by: AccountId,
+        weights: Vec<AssetWeight>,
+    },
+
+    AssetRemoved {
+        asset_address: AccountId,
+    },
+
+    OwnershipRenounced {
+        previous_owner: AccountId,
+    },
+
+    UpgradeProposed {
+        code_hash: Base58CryptoHash,
+        eta: U64,
+    },
+
+    EmergencyWithdraw {
+        by: AccountId,
+        asset_address: AccountId,
+        amount: U128,
+        recipient: AccountId,
+    },
+
+    AssetMigrated {
+        old_address: AccountId,
+        new_address: AccountId,
+    },
+
+    WindDownInitiated {
+        by: AccountId,
+    },
+
+    FundClosed {
+        by: AccountId,
+    },
+
+    HoldingOverwritten {
+        by: AccountId,
+        asset_address: AccountId,
+        balance: U128,
+        weight: U64,
+        last_price: U128,
+    },
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct EventLog<'a> {
+    standard: &'a str,
+    version: &'a str,
+    #[serde(flatten)]
+    event: &'a IndexFundEvent,
+}
+
+impl IndexFundEvent {
+    pub fn emit(&self, standard: &str, version: &str) {
+        let log = EventLog {
+            standard,
+            version,
+            event: self,
+        };
+        env::log_str(&format!(
+            "EVENT_JSON:{}",
+            near_sdk::serde_json::to_string(&log).unwrap()
+        ));
+    }
+}
@@ -1,15 +1,85 @@
 // Find all our documentation at https://docs.near.org
-use near_sdk::collections::UnorderedMap;
-use near_sdk::json_types::{U128, U64};
-use near_sdk::{env, require, AccountId};
+use near_contract_standards::fungible_token::core::ext_ft_core;
+use near_contract_standards::fungible_token::events::FtTransfer;
+use near_contract_standards::fungible_token::metadata::{
+    ext_ft_metadata, FungibleTokenMetadata, FungibleTokenMetadataProvider, FT_METADATA_SPEC,
+};
+use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+use near_contract_standards::storage_management::{
+    StorageBalance, StorageBalanceBounds, StorageManagement,
+};
+use near_sdk::collections::{LookupMap, UnorderedMap, Vector};
+use near_sdk::json_types::{Base58CryptoHash, U128, U64};
+use near_sdk::{
+    assert_one_yocto, env, ext_contract, log, require, AccountId, BorshStorageKey, Gas, GasWeight,
+    PanicOnDefault, Promise, PromiseError, PromiseOrValue, PromiseResult, StorageUsage,
+};
 use near_sdk::{near, NearToken};
 
-use std::str::FromStr;
+pub mod events;
+use events::IndexFundEvent;
 
 pub type AssetId = AccountId;
 pub type Price = U128;
 
-#[derive(Debug)]
+// `ft_transfer_call` msg that parks tokens for a later `deposit_proportional`
+pub const PROPORTIONAL_DEPOSIT_MSG: &str = "proportional";
+// `ft_transfer_call` msg the DEX uses to hand back the proceeds of a liquidation
+pub const LIQUIDATION_MSG: &str = "liquidation";
+// Weights are expressed in parts of this denominator (basis points)
+pub const WEIGHT_DENOMINATOR: u64 = 10000;
+// Shortest rebalance interval `set_rebalance_interval` accepts, so trades can't be churned
+pub const MIN_REBALANCE_INTERVAL_BLOCKS: u64 = 100;
+// Non-zero weights at or below this many basis points are likely rounding artifacts
+pub const DUST_WEIGHT_BPS: u64 = 1;
+// Block time assumed when converting intervals between blocks and milliseconds
+pub const MS_PER_BLOCK: u64 = 1000;
+// Fixed-point scale for NAV-per-share values (1.0 = 10^18)
+pub const NAV_PER_SHARE_SCALE: u128 = 1_000_000_000_000_000_000;
+// Number of NAV snapshots kept before the oldest is overwritten
+pub const MAX_NAV_HISTORY: u64 = 1000;
+// Number of action records kept before the oldest is overwritten
+pub const MAX_ACTION_HISTORY: u64 = 1000;
+// Number of curator changes kept before the oldest is overwritten
+pub const MAX_CURATOR_HISTORY: u64 = 100;
+// Allowed deviation of a proportional deposit from the portfolio allocation, in basis points
+pub const PROPORTIONAL_DEPOSIT_TOLERANCE_BPS: u128 = 50;
+// Share decimals at which the first deposit mints one share unit per quote unit; fewer decimals
+// mint proportionally fewer units
+pub const SHARE_BASE_DECIMALS: u8 = 24;
+// Length of the year management fees are quoted over
+pub const NS_PER_YEAR: u64 = 365 * 24 * 60 * 60 * 1_000_000_000;
+// Shortest notice before a proposed upgrade can be deployed, so holders have time to exit
+pub const MIN_UPGRADE_DELAY_NS: u64 = 2 * 24 * 60 * 60 * 1_000_000_000;
+// Bytes NEAR charges for each stored key-value record on top of the key and value themselves
+pub const STORAGE_RECORD_OVERHEAD: u64 = 40;
+// Time a weight commitment must sit before it can be revealed
+pub const WEIGHT_REVEAL_DELAY_NS: u64 = 10 * 60 * 1_000_000_000;
+// Transfer fee assumed for assets flagged `fee_on_transfer` when sizing rebalance buys
+pub const FEE_ON_TRANSFER_ALLOWANCE_BPS: i128 = 100;
+// Longest asset label accepted, in bytes
+pub const MAX_LABEL_LEN: usize = 32;
+// Highest keeper reward per needed rebalance, in basis points of the supply
+pub const MAX_KEEPER_REWARD_BPS: u64 = 50;
+
+const GAS_FOR_FT_TRANSFER: Gas = Gas::from_tgas(10);
+const GAS_FOR_RESOLVE_TRANSFER: Gas = Gas::from_tgas(5);
+const GAS_FOR_FT_TRANSFER_CALL: Gas = Gas::from_tgas(100);
+const GAS_FOR_RESOLVE_LIQUIDATION: Gas = Gas::from_tgas(10);
+const GAS_FOR_NEAR_DEPOSIT: Gas = Gas::from_tgas(10);
+const GAS_FOR_FT_METADATA: Gas = Gas::from_tgas(5);
+const GAS_FOR_RESOLVE_VALIDATION: Gas = Gas::from_tgas(30);
+const GAS_FOR_RESOLVE_WRAP: Gas = Gas::from_tgas(20);
+// floor for the post-upgrade `migrate` call, which also gets all gas left unused
+const GAS_FOR_MIGRATE: Gas = Gas::from_tgas(50);
+
+// The wrapped-NEAR token contract (e.g. `wrap.near`)
+#[ext_contract(ext_wrap_near)]
+pub trait WrapNear {
+    fn near_deposit(&mut self);
+}
+
+#[derive(Debug, Clone)]
 #[near(serializers = [json, borsh])]
 pub struct AssetWeight {
     pub weight: U64, // Basis points (e.g., 5000 = 50%)
@@ -22,27 +92,499 @@ pub struct AssetHolding {
     pub weight: U64,
     pub last_price: U128,
     pub last_updated: U64,
+    // curator switch to stop accepting deposits of this asset (e.g. during a depeg)
+    pub deposits_enabled: bool,
+    // block timestamp before which rebalancing won't trade this asset (e.g. a lockup)
+    pub tradeable_after_ns: U64,
+    // token decimals; `last_price` is quoted per whole token
+    pub decimals: u8,
+    // largest balance the fund will hold; deposits beyond it are refunded
+    pub max_balance: Option<U128>,
+    // curator tag for grouping in UIs, e.g. "stablecoin"
+    pub label: Option<String>,
+    // range the target weight must stay in, in basis points
+    pub min_weight: U64,
+    pub max_weight: U64,
+    // oracle-reported market capitalization, for cap-weighted indexes
+    pub market_cap: U128,
+    // the token deducts a fee on transfer, so buys are sized up to land on target
+    pub fee_on_transfer: bool,
+    // feed that prices this asset instead of the fund's oracle
+    pub oracle: Option<AccountId>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[near(serializers = [json, borsh])]
+pub enum IntervalUnit {
+    Blocks,
+    Milliseconds, // block timestamp
+}
+
+// Order of the trades in a rebalance plan. `Proportional` lists trades in asset order, so an
+// executor may need outside capital to fund buys that come before the sells. `SellFirst` lists
+// every sell ahead of every buy, so buys can be paid for out of sale proceeds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[near(serializers = [json, borsh])]
+pub enum RebalanceStrategy {
+    SellFirst,
+    Proportional,
+}
+
+// What happens to the rounding dust of a redemption. Each asset's payout is rounded down, so a
+// redeemer gets slightly less value than their shares are worth. `LeaveInFund` keeps that
+// shortfall in the fund, nudging NAV per share up for the remaining holders. `SendWithLargest`
+// converts the shortfall back into units of the redeemer's largest payout (by value), so the
+// redeemer gets what their shares are worth to within one unit of that asset, and NAV per share
+// stays put.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[near(serializers = [json, borsh])]
+pub enum DustPolicy {
+    LeaveInFund,
+    SendWithLargest,
+}
+
+// What a deposit does with value left in the fund once every share has been redeemed (rounding
+// dust, tokens sent in directly). `CleanMint` mints the usual 1:1 first-deposit shares, so the
+// deposit's value gets the leftovers on top. `SweepToFeeRecipient` first mints the leftovers'
+// worth of shares, at the same 1:1 rate, to the fee recipient, so the depositor gets exactly
+// what they paid in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[near(serializers = [json, borsh])]
+pub enum ZeroSupplyPolicy {
+    CleanMint,
+    SweepToFeeRecipient,
+}
+
+// How far a rebalance moves each asset. `Full` trades every asset to its exact target. `Band`
+// leaves assets within `width_bps` (of NAV) of their target alone and only brings the others
+// back to the nearest band edge, so buys and sells generally don't net to zero.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[near(serializers = [json, borsh])]
+pub enum RebalanceMode {
+    Full,
+    Band { width_bps: U64 },
+}
+
+// Gradual move from `from` to the stored (target) weights, linear over `glide_blocks` blocks
+#[near(serializers = [json, borsh])]
+pub struct WeightGlide {
+    pub from: Vec<AssetWeight>,
+    pub start_block: U64,
+    pub glide_blocks: U64,
+}
+
+// A `remove_asset_into` swap waiting on the DEX
+#[derive(Debug, Clone, PartialEq)]
+#[near(serializers = [json, borsh])]
+pub struct Liquidation {
+    pub asset_id: AssetId,
+    pub token_out: AssetId,
+    pub amount: U128,
+}
+
+#[derive(Debug, PartialEq)]
+#[near(serializers = [json, borsh])]
+pub struct UpgradeProposal {
+    pub code_hash: Base58CryptoHash, // sha256 of the wasm
+    pub eta: U64,                    // earliest block timestamp it can be deployed at
+}
+
+// Hidden weight update: sha256 of the borsh-serialized `(updates, salt)`
+#[derive(Debug, PartialEq)]
+#[near(serializers = [json, borsh])]
+pub struct WeightCommitment {
+    pub hash: Base58CryptoHash,
+    pub committed_at: U64,
+}
+
+// An asset to onboard ahead of allocating to it, see `register_assets`
+#[near(serializers = [json])]
+pub struct AssetRegistration {
+    pub asset_id: AssetId,
+    pub decimals: u8,
+    pub label: Option<String>,
+}
+
+// One step of a curator `batch`; each runs with the same authorization as the standalone method
+#[near(serializers = [json])]
+pub enum CuratorAction {
+    UpdateWeights { updates: Vec<AssetWeight> },
+    UpdatePrices { prices: Vec<(AssetId, Price)> },
+    Rebalance,
+}
+
+// Every persistent collection gets its own key here. Each variant serializes to a distinct single
+// byte, so no collection's prefix can be a prefix of another's (or of the `STATE` key).
+#[derive(BorshStorageKey)]
+#[near(serializers = [borsh])]
+pub enum StorageKey {
+    Assets,
+    Shares,
+    PendingDeposits,
+    RedemptionQueue,
+    NavHistory,
+    LastDeposit,
+    CuratorActions,
+    CuratorHistory,
+}
+
+#[derive(Debug, PartialEq)]
+#[near(serializers = [json, borsh])]
+pub struct RedemptionRequest {
+    pub account: AccountId,
+    pub amounts: Vec<(AssetId, U128)>,
+    pub requested_at: U64, // block timestamp
+}
+
+#[derive(Debug, PartialEq)]
+#[near(serializers = [json, borsh])]
+pub struct NavSnapshot {
+    pub block_height: U64,
+    pub timestamp: U64,
+    pub nav: U128,
+    pub nav_per_share: U128, // scaled by NAV_PER_SHARE_SCALE
+}
+
+// Presentation of the share token; `icon` is a data URL as in NEP-148
+#[derive(Debug, Clone, PartialEq)]
+#[near(serializers = [json, borsh])]
+pub struct FundMetadata {
+    pub name: String,
+    pub symbol: String,
+    pub icon: Option<String>,
+    pub reference: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+#[near(serializers = [json])]
+pub struct IndexConstituent {
+    pub asset_id: AssetId,
+    pub label: Option<String>,
+    pub weight: U64,    // target weight
+    pub value_bps: U64, // realized share of NAV, see `get_value_weights`
+}
+
+#[derive(Debug, PartialEq)]
+#[near(serializers = [json])]
+pub struct IndexListing {
+    pub metadata: FundMetadata,
+    pub constituents: Vec<IndexConstituent>,
+}
+
+// One point of `get_share_price_history`; `index` is the snapshot's position in the NAV history
+#[derive(Debug, PartialEq)]
+#[near(serializers = [json])]
+pub struct SharePricePoint {
+    pub index: u64,
+    pub timestamp: U64,
+    pub nav_per_share: U128, // scaled by NAV_PER_SHARE_SCALE
+}
+
+#[derive(Debug, PartialEq)]
+#[near(serializers = [json, borsh])]
+pub struct CuratorChange {
+    pub old: Option<AccountId>,
+    pub new: AccountId,
+    pub block_height: U64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[near(serializers = [json, borsh])]
+pub enum ActionKind {
+    UpdateWeights,
+    UpdatePrices,
+    Pause,
+    Unpause,
+}
+
+#[derive(Debug, PartialEq)]
+#[near(serializers = [json, borsh])]
+pub struct ActionRecord {
+    pub action: ActionKind,
+    pub block_height: U64,
+    pub caller: AccountId,
+}
+
+#[derive(Debug, PartialEq)]
+#[near(serializers = [json])]
+pub struct RebalanceEstimate {
+    pub num_trades: u64,
+    // sum of the absolute value of every trade, in quote-asset units
+    pub total_notional: U128,
+    pub nav: U128,
+}
+
+#[derive(Debug, PartialEq)]
+#[near(serializers = [json])]
+pub enum BreachKind {
+    AboveMax,
+    BelowMin,
+}
+
+#[derive(Debug, PartialEq)]
+#[near(serializers = [json])]
+pub struct PriceHealth {
+    pub asset_id: AssetId,
+    pub price: U128,
+    pub age_ns: U64,
+    // unpriced, or older than `max_price_age_ns`
+    pub is_stale: bool,
+}
+
+#[derive(Debug, PartialEq)]
+#[near(serializers = [json])]
+pub struct FeeConfig {
+    pub entry_fee_bps: U64,
+    pub exit_fee_bps: U64,
+    pub management_fee_bps: U64, // per year
+    pub keeper_reward_bps: U64,  // per needed rebalance
+    pub fee_recipient: Option<AccountId>,
+}
+
+#[near(serializers = [json])]
+pub struct StateChunk {
+    // holdings (with weights and prices) sorted by asset id
+    pub holdings: Vec<(AssetId, AssetHolding)>,
+    // where the next chunk starts; None once everything has been exported
+    pub next_index: Option<u64>,
+    pub total_assets: u64,
+}
+
+#[derive(Debug, PartialEq)]
+#[near(serializers = [json])]
+pub struct WeightDiff {
+    pub asset_address: AssetId,
+    pub old: U64,
+    pub new: U64,
+    pub delta: i64, // new - old, in basis points
+}
+
+#[derive(Debug, PartialEq)]
+#[near(serializers = [json])]
+pub struct RebalanceReadiness {
+    pub interval_elapsed: bool,
+    // until the interval elapses, in `interval_unit`
+    pub blocks_remaining: U64,
+    // largest gap between an asset's value allocation and its target weight
+    pub max_drift_bps: U64,
+    pub tolerance_bps: U64,
+    // interval elapsed and drift beyond tolerance
+    pub ready: bool,
+}
+
+#[derive(Debug, PartialEq)]
+#[near(serializers = [json])]
+pub struct PortfolioStats {
+    pub asset_count: u64,
+    // assets with a non-zero target weight
+    pub active_asset_count: u64,
+    pub nav: U128,
+    pub total_shares: U128,
+    pub nav_per_share: U128, // scaled by NAV_PER_SHARE_SCALE
+    pub total_weight: U64,
+}
+
+#[derive(Debug, PartialEq)]
+#[near(serializers = [json])]
+pub struct AssetsStorageInfo {
+    pub count: u64,
+    pub approx_bytes: u64,
 }
 
-// Define the contract structure
+// Define the contract structure. There's no `Default`: a contract that was deployed but never
+// `new`-initialized panics on every call instead of running as an unconfigured fund.
 #[near(contract_state)]
+#[derive(PanicOnDefault)]
 pub struct IndexFund {
     // a Curator is normally a DAO, but could be any account
     pub curator_address: Option<AccountId>,
     pub assets: UnorderedMap<AssetId, AssetHolding>,
     pub last_rebalance: U64,
-    // TODO: (LP) add support for milliseconds in addition to blocks
-    pub rebalance_interval: U64, // blocks
+    pub rebalance_interval: U64, // in `interval_unit`
+    // share balances; an entry (even with zero shares) means the account is storage-registered
+    pub shares: UnorderedMap<AccountId, U128>,
+    pub total_shares: U128,
+    // bytes needed to register a single share holder
+    pub account_storage_usage: StorageUsage,
+    // account allowed to push asset prices
+    pub oracle_address: Option<AccountId>,
+    // largest accepted single-update price move, in basis points (0 = unlimited)
+    pub max_price_move_bps: U64,
+    // tokens transferred for a proportional deposit that haven't been deposited yet
+    pub pending_deposits: LookupMap<(AccountId, AssetId), U128>,
+    // blocks deposits and weight updates while set
+    pub paused: bool,
+    // set only while ft_on_transfer runs
+    pub processing_transfer: bool,
+    // deployer/admin account, separate from the curator who manages the index
+    pub owner_id: Option<AccountId>,
+    // receives shares taken as fees
+    pub fee_recipient: Option<AccountId>,
+    // share of newly minted shares taken on deposit, in basis points
+    pub entry_fee_bps: U64,
+    // share of redemption payouts kept in the fund for remaining holders, in basis points
+    pub exit_fee_bps: U64,
+    // redemptions waiting for delivery; entries before the head have been processed
+    pub redemption_queue: Vector<RedemptionRequest>,
+    pub redemption_queue_head: u64,
+    // may record NAV snapshots alongside the owner
+    pub keeper_id: Option<AccountId>,
+    // ring buffer of at most MAX_NAV_HISTORY snapshots; the oldest lives at `nav_history_start`
+    pub nav_history: Vector<NavSnapshot>,
+    pub nav_history_start: u64,
+    // unit of `last_rebalance` and `rebalance_interval`
+    pub interval_unit: IntervalUnit,
+    pub rebalance_count: U64,
+    // smallest deposit accepted, in quote-asset value
+    pub min_deposit_value: U128,
+    // sub-manager allowed to nudge weights by at most `delegate_max_delta_bps` per asset and call
+    pub delegate: Option<AccountId>,
+    pub delegate_max_delta_bps: U64,
+    pub rebalance_strategy: RebalanceStrategy,
+    // set while the effective weights are still gliding towards the stored ones
+    pub weight_glide: Option<WeightGlide>,
+    // yearly fee, taken by minting shares to the fee recipient, in basis points
+    pub management_fee_bps: U64,
+    // block timestamp up to which the management fee has been taken
+    pub management_fee_accrued_at: U64,
+    // oracle nominated by the current one, waiting to accept
+    pub proposed_oracle: Option<AccountId>,
+    // decimals of the share token, at most SHARE_BASE_DECIMALS
+    pub share_decimals: u8,
+    // reject (rather than just log) weight updates that zero an asset still holding a balance
+    pub strict_weight_zeroing: bool,
+    // tie-breaker for `get_weights_sorted_seeded`
+    pub ordering_seed: U64,
+    // how far weight updates may miss 100% and still be accepted (after renormalizing)
+    pub weight_sum_tolerance_bps: U64,
+    pub proposed_upgrade: Option<UpgradeProposal>,
+    // shares can't be redeemed or transferred until this long after the holder's last deposit
+    pub share_lockup_ns: U64,
+    // block timestamp of each holder's latest deposit
+    pub last_deposit_at: LookupMap<AccountId, U64>,
+    // prices older than this are reported as stale (0 = never)
+    pub max_price_age_ns: U64,
+    // after an unpause, deposits and weight changes stay blocked this long (redemptions don't)
+    pub unpause_grace_ns: U64,
+    pub unpaused_at: U64,
+    // block timestamp the fund was created at
+    pub inception_ts: U64,
+    // swaps assets being liquidated; proceeds come back as a LIQUIDATION_MSG transfer
+    pub dex_id: Option<AccountId>,
+    // deposits and redemptions wait while it's set: the asset being sold is off the books
+    pub liquidation: Option<Liquidation>,
+    // drift (in basis points of NAV) a rebalance is considered needed beyond
+    pub drift_tolerance_bps: U64,
+    // drop holdings left with zero weight and zero balance after a weight update
+    pub auto_prune_zero_weight: bool,
+    // audit log of weight, price and pause operations; a ring buffer like `nav_history`
+    pub curator_actions: Vector<ActionRecord>,
+    pub curator_actions_start: u64,
+    // wrapped-NEAR token that native NEAR deposits are converted to; must be one of the assets
+    pub wrap_near_id: Option<AccountId>,
+    // hard cap on outstanding shares; deposits that would mint past it are rejected
+    pub max_share_supply: Option<U128>,
+    // may change the fund metadata and asset labels, but nothing financial
+    pub reporter: Option<AccountId>,
+    pub metadata: Option<FundMetadata>,
+    // shares minted to whoever triggers a needed rebalance, in basis points of the supply
+    pub keeper_reward_bps: U64,
+    pub weight_commitment: Option<WeightCommitment>,
+    // stops price ingestion (e.g. a suspected oracle compromise); the last prices stay in use
+    pub prices_frozen: bool,
+    pub dust_policy: DustPolicy,
+    // check that new assets are live NEP-141 tokens (via `ft_metadata`) before adding them
+    pub validate_assets_on_add: bool,
+    pub rebalance_mode: RebalanceMode,
+    // set by the curator, becomes the curator on `accept_curator`
+    pub proposed_curator: Option<AccountId>,
+    // every curator change; a ring buffer like `nav_history`
+    pub curator_history: Vector<CuratorChange>,
+    pub curator_history_start: u64,
+    // orderly shutdown: deposits are refused, and every holder may redeem regardless of lockup
+    pub winding_down: bool,
+    // NEP-297 `standard` and `version` of the fund's events
+    pub event_standard: String,
+    pub event_version: String,
+    // lifetime entry, exit and management fees and keeper rewards, valued in the quote asset when
+    // taken
+    pub total_fees_collected: U128,
+    pub zero_supply_policy: ZeroSupplyPolicy,
 }
 
-impl Default for IndexFund {
-    fn default() -> Self {
-        Self {
+impl IndexFund {
+    // Initial state that `new` fills in its arguments on
+    fn with_defaults() -> Self {
+        let mut this = Self {
             curator_address: None,
-            assets: UnorderedMap::new(b"a"),
+            assets: UnorderedMap::new(StorageKey::Assets),
             last_rebalance: U64(0),
             rebalance_interval: U64(86400), // ~1 day assuming 1 block per second
-        }
+            shares: UnorderedMap::new(StorageKey::Shares),
+            total_shares: U128(0),
+            account_storage_usage: 0,
+            oracle_address: None,
+            max_price_move_bps: U64(0),
+            pending_deposits: LookupMap::new(StorageKey::PendingDeposits),
+            paused: false,
+            processing_transfer: false,
+            owner_id: None,
+            fee_recipient: None,
+            entry_fee_bps: U64(0),
+            exit_fee_bps: U64(0),
+            redemption_queue: Vector::new(StorageKey::RedemptionQueue),
+            redemption_queue_head: 0,
+            keeper_id: None,
+            nav_history: Vector::new(StorageKey::NavHistory),
+            nav_history_start: 0,
+            interval_unit: IntervalUnit::Blocks,
+            rebalance_count: U64(0),
+            min_deposit_value: U128(0),
+            delegate: None,
+            delegate_max_delta_bps: U64(0),
+            rebalance_strategy: RebalanceStrategy::Proportional,
+            weight_glide: None,
+            management_fee_bps: U64(0),
+            management_fee_accrued_at: U64(env::block_timestamp()),
+            proposed_oracle: None,
+            share_decimals: SHARE_BASE_DECIMALS,
+            strict_weight_zeroing: false,
+            ordering_seed: U64(0),
+            weight_sum_tolerance_bps: U64(0),
+            proposed_upgrade: None,
+            share_lockup_ns: U64(0),
+            last_deposit_at: LookupMap::new(StorageKey::LastDeposit),
+            max_price_age_ns: U64(0),
+            unpause_grace_ns: U64(0),
+            unpaused_at: U64(0),
+            inception_ts: U64(env::block_timestamp()),
+            dex_id: None,
+            liquidation: None,
+            drift_tolerance_bps: U64(0),
+            auto_prune_zero_weight: false,
+            curator_actions: Vector::new(StorageKey::CuratorActions),
+            curator_actions_start: 0,
+            wrap_near_id: None,
+            max_share_supply: None,
+            reporter: None,
+            metadata: None,
+            keeper_reward_bps: U64(0),
+            weight_commitment: None,
+            prices_frozen: false,
+            dust_policy: DustPolicy::LeaveInFund,
+            validate_assets_on_add: false,
+            rebalance_mode: RebalanceMode::Full,
+            proposed_curator: None,
+            curator_history: Vector::new(StorageKey::CuratorHistory),
+            curator_history_start: 0,
+            winding_down: false,
+            event_standard: events::EVENT_STANDARD.to_string(),
+            event_version: events::EVENT_VERSION.to_string(),
+            total_fees_collected: U128(0),
+            zero_supply_policy: ZeroSupplyPolicy::CleanMint,
+        };
+        this.measure_account_storage_usage();
+        this
     }
 }
 
@@ -50,13 +592,20 @@ impl Default for IndexFund {
 #[near]
 impl IndexFund {
     #[init]
-    pub fn new(rebalance_interval: U64) -> Self {
+    pub fn new(
+        rebalance_interval: U64,
+        dust_policy: Option<DustPolicy>,
+        event_standard: Option<String>,
+        event_version: Option<String>,
+    ) -> Self {
         require!(rebalance_interval > U64(0), "Invalid rebalance interval");
         Self {
-            curator_address: None,
-            assets: UnorderedMap::new(b"a"),
-            last_rebalance: U64(0),
             rebalance_interval,
+            dust_policy: dust_policy.unwrap_or(DustPolicy::LeaveInFund),
+            event_standard: event_standard.unwrap_or_else(|| events::EVENT_STANDARD.to_string()),
+            event_version: event_version.unwrap_or_else(|| events::EVENT_VERSION.to_string()),
+            owner_id: Some(env::predecessor_account_id()),
+            ..Self::with_defaults()
         }
     }
 
@@ -68,179 +617,7612 @@ impl IndexFund {
                 >= NearToken::from_yoctonear(env::storage_byte_cost().as_yoctonear() * 100),
             "Insufficient storage deposit"
         );
-        self.curator_address = Some(curator_address);
+        self.set_curator(curator_address);
+    }
+
+    pub fn propose_curator(&mut self, new_curator: AccountId) {
+        self.assert_curator();
+        self.proposed_curator = Some(new_curator);
+    }
+
+    pub fn accept_curator(&mut self) {
+        let caller = env::predecessor_account_id();
+        require!(
+            self.proposed_curator.as_ref() == Some(&caller),
+            "Unauthorized"
+        );
+        self.proposed_curator = None;
+        self.set_curator(caller);
     }
 
+    /// The curator can set any weights; the delegate can only move each asset's weight by up to
+    /// `delegate_max_delta_bps`. With `validate_assets_on_add`, updates that add assets are only
+    /// applied once every new token has answered `ft_metadata`; they're checked up front and
+    /// again when applied.
     pub fn update_weights(&mut self, updates: Vec<AssetWeight>) {
-        let curator = self
-            .curator_address
-            .as_ref()
-            .expect("curator not registered");
-        require!(env::predecessor_account_id() == *curator, "Unauthorized");
+        let caller = env::predecessor_account_id();
+        self.assert_can_update_weights(&caller, &updates);
+        self.assert_active();
 
-        // Create a temporary copy of current weights
-        let mut new_weights: std::collections::HashMap<AccountId, U64> =
-            self.assets.iter().map(|(k, v)| (k, v.weight)).collect();
+        let new_assets = self.new_assets(&updates);
+        if self.validate_assets_on_add && !new_assets.is_empty() {
+            self.checked_weight_updates(&updates);
+            log!("Validating new assets {:?}", new_assets);
+            let checks = new_assets
+                .into_iter()
+                .map(|asset_id| {
+                    ext_ft_metadata::ext(asset_id)
+                        .with_static_gas(GAS_FOR_FT_METADATA)
+                        .ft_metadata()
+                })
+                .reduce(|all, check| all.and(check))
+                .unwrap();
+            checks.then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_VALIDATION)
+                    .on_assets_validated(caller, updates),
+            );
+            return;
+        }
 
-        // Apply updates
-        for update in updates.iter() {
-            new_weights.insert(update.asset_address.clone(), update.weight);
+        self.internal_update_weights(&updates);
+    }
+
+    /// Applies a weight update deferred by `validate_assets_on_add` if every promise result (one
+    /// `ft_metadata` per new asset) came back successfully and the update is still allowed.
+    #[private]
+    pub fn on_assets_validated(&mut self, caller: AccountId, updates: Vec<AssetWeight>) -> bool {
+        let failed = (0..env::promise_results_count())
+            .any(|index| !matches!(env::promise_result(index), PromiseResult::Successful(_)));
+        if failed {
+            log!("A new asset isn't a fungible token, weights not updated");
+            return false;
         }
+        // the fund may have changed while the tokens were queried
+        self.assert_can_update_weights(&caller, &updates);
+        self.assert_active();
+        self.assert_no_liquidation();
+        self.internal_update_weights(&updates);
+        true
+    }
 
-        // Verify total weight is 10000 (100%)
-        let total_weight: u64 = new_weights.values().map(|&w| u64::from(w)).sum();
-        require!(total_weight == 10000, "Final weights must sum to 100%");
+    pub fn set_validate_assets_on_add(&mut self, enabled: bool) {
+        self.assert_curator();
+        self.validate_assets_on_add = enabled;
+    }
 
-        // Apply updates only after verification
-        for update in &updates {
-            if let Some(mut holding) = self.assets.get(&update.asset_address) {
-                holding.weight = update.weight;
-                self.assets.insert(&update.asset_address, &holding);
-            } else {
-                self.assets.insert(
-                    &update.asset_address,
-                    &AssetHolding {
-                        balance: U128(0),
-                        weight: update.weight,
-                        last_price: U128(0),
-                        last_updated: U64(env::block_timestamp()),
-                    },
-                );
+    /// Sets new target weights that rebalancing glides towards linearly over `glide_blocks`
+    /// blocks, starting from the current effective weights. `get_weights` shows the targets right
+    /// away; `get_effective_weights` shows where the glide currently is.
+    pub fn set_target_weights(&mut self, updates: Vec<AssetWeight>, glide_blocks: U64) {
+        self.assert_curator();
+        self.assert_active();
+        require!(glide_blocks.0 > 0, "Invalid glide duration");
+
+        let from = self.get_effective_weights();
+        self.internal_update_weights(&updates);
+        self.weight_glide = Some(WeightGlide {
+            from,
+            start_block: U64(env::block_height()),
+            glide_blocks,
+        });
+    }
+
+    /// Adds assets with zero weight and balance, so onboarding (decimals, labels) can happen
+    /// before allocating to them with `update_weights`.
+    pub fn register_assets(&mut self, assets: Vec<AssetRegistration>) {
+        self.assert_curator();
+        for registration in assets {
+            require!(
+                self.assets.get(&registration.asset_id).is_none(),
+                format!("{} is already registered", registration.asset_id)
+            );
+            if let Some(label) = registration.label.as_ref() {
+                require!(label.len() <= MAX_LABEL_LEN, "Label too long");
+            }
+
+            let mut holding = Self::new_holding(U64(0));
+            holding.decimals = registration.decimals;
+            holding.label = registration.label;
+            self.assets.insert(&registration.asset_id, &holding);
+            IndexFundEvent::AssetAdded {
+                asset_address: registration.asset_id,
+                initial_weight: U64(0),
             }
+            .emit(&self.event_standard, &self.event_version);
         }
+    }
 
-        env::log_str(&format!("Updated weights: {:?}", updates));
+    /// First half of a commit-reveal weight update: records the hash of the intended weights and
+    /// a salt (see `hash_weights`) without disclosing them. Replaces any earlier commitment.
+    pub fn commit_weights(&mut self, hash: Base58CryptoHash) {
+        self.assert_curator();
+        self.weight_commitment = Some(WeightCommitment {
+            hash,
+            committed_at: U64(env::block_timestamp()),
+        });
     }
 
-    pub fn get_weights(&self) -> Vec<AssetWeight> {
-        self.assets
-            .iter()
-            .map(|(asset_address, h)| AssetWeight {
-                weight: h.weight,
-                asset_address,
-            })
-            .collect()
+    /// Applies committed weights once WEIGHT_REVEAL_DELAY_NS has passed since the commitment.
+    pub fn reveal_weights(&mut self, updates: Vec<AssetWeight>, salt: String) {
+        self.assert_curator();
+        self.assert_active();
+        let commitment = self
+            .weight_commitment
+            .as_ref()
+            .expect("No weight commitment");
+        require!(
+            env::block_timestamp() >= commitment.committed_at.0 + WEIGHT_REVEAL_DELAY_NS,
+            "Reveal delay has not elapsed"
+        );
+        require!(
+            Self::weights_hash(&updates, &salt) == commitment.hash,
+            "Weights don't match the commitment"
+        );
+
+        self.weight_commitment = None;
+        self.internal_update_weights(&updates);
     }
 
-    pub fn get_assets(&self) -> Vec<AssetId> {
-        self.assets.keys().collect()
+    /// Runs `actions` in order within one transaction. A failing action panics, which reverts the
+    /// whole batch.
+    pub fn batch(&mut self, actions: Vec<CuratorAction>) {
+        self.assert_curator();
+        for action in actions {
+            match action {
+                CuratorAction::UpdateWeights { updates } => {
+                    // validation would defer the update, and the rest of the batch would run
+                    // against the old weights
+                    require!(
+                        !self.validate_assets_on_add || self.new_assets(&updates).is_empty(),
+                        "Can't add assets in a batch while they're validated"
+                    );
+                    self.update_weights(updates)
+                }
+                CuratorAction::UpdatePrices { prices } => self.update_prices(prices),
+                CuratorAction::Rebalance => {
+                    self.rebalance();
+                }
+            }
+        }
     }
-}
 
-/*
- * =====================================================================
- * --------------------------------Tests--------------------------------
- * =====================================================================
- */
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use near_sdk::test_utils::VMContextBuilder;
-    use near_sdk::testing_env;
+    /// Break-glass weight override for the owner. Skips the curator checks (but not the 100% sum)
+    /// and leaves the contract paused.
+    pub fn emergency_set_weights(&mut self, updates: Vec<AssetWeight>) {
+        self.assert_owner();
+        self.internal_update_weights(&updates);
 
-    fn get_context(predecessor: AccountId) -> VMContextBuilder {
-        let mut builder = VMContextBuilder::new();
-        builder.predecessor_account_id(predecessor);
-        builder.block_timestamp(100);
-        builder
+        let by = env::predecessor_account_id();
+        IndexFundEvent::EmergencyWeightsSet {
+            by: by.clone(),
+            weights: updates,
+        }
+        .emit(&self.event_standard, &self.event_version);
+        if !self.paused {
+            self.paused = true;
+            IndexFundEvent::Paused { by }.emit(&self.event_standard, &self.event_version);
+        }
     }
 
-    #[test]
-    fn test_default_index_fund() {
-        let contract = IndexFund::default();
-        assert!(contract.curator_address.is_none());
-        assert_eq!(contract.last_rebalance, U64(0));
-        assert_eq!(contract.rebalance_interval, U64(86400));
-        assert_eq!(contract.get_assets().len(), 0);
+    /// Removes an asset that holds nothing. Other assets must already carry the full weight, and
+    /// the last asset can only go once no shares are outstanding (NAV would be undefined).
+    pub fn remove_asset(&mut self, asset_id: AssetId) {
+        self.assert_curator();
+        self.assert_no_liquidation();
+        let holding = self.expect_asset(&asset_id);
+        require!(
+            holding.balance.0 == 0,
+            "Can't remove an asset with a balance"
+        );
+        if self.assets.len() == 1 {
+            require!(
+                self.total_shares.0 == 0,
+                "Can't remove the last asset while shares are outstanding"
+            );
+        } else {
+            require!(holding.weight.0 == 0, "Asset still has weight");
+        }
+
+        self.assets.remove(&asset_id);
+        IndexFundEvent::AssetRemoved {
+            asset_address: asset_id,
+        }
+        .emit(&self.event_standard, &self.event_version);
     }
 
-    #[test]
-    fn test_update_weights() {
-        let curator = AccountId::from_str("curator.near").unwrap();
-        let asset1 = AccountId::from_str("asset1.near").unwrap();
-        let asset2 = AccountId::from_str("asset2.near").unwrap();
+    /// Removes a funded asset by swapping its whole balance into `liquidate_into` on the DEX.
+    /// The asset's tokens go to the DEX with `ft_transfer_call` and the swap output comes back
+    /// as a LIQUIDATION_MSG transfer of `liquidate_into`. The asset is only deleted once the DEX
+    /// has used the full amount; otherwise the unused part is restored and the asset stays.
+    /// Deposits and redemptions are refused until the swap resolves.
+    pub fn remove_asset_into(&mut self, asset_id: AssetId, liquidate_into: AssetId) -> Promise {
+        self.assert_curator();
+        let dex_id = self.dex_id.clone().expect("DEX not set");
+        self.assert_no_liquidation();
+        require!(
+            asset_id != liquidate_into,
+            "Can't liquidate an asset into itself"
+        );
+        self.expect_asset(&liquidate_into);
+        let mut holding = self.expect_asset(&asset_id);
+        require!(holding.weight.0 == 0, "Asset still has weight");
+        let amount = holding.balance;
+        require!(amount.0 > 0, "Nothing to liquidate, use remove_asset");
 
-        let context = get_context(curator.clone());
-        testing_env!(context.build());
+        // off the books while the swap is in flight, and closed to new deposits
+        holding.balance = U128(0);
+        holding.deposits_enabled = false;
+        self.assets.insert(&asset_id, &holding);
+        self.liquidation = Some(Liquidation {
+            asset_id: asset_id.clone(),
+            token_out: liquidate_into.clone(),
+            amount,
+        });
 
-        let mut contract = IndexFund::default();
-        contract.curator_address = Some(curator);
+        let msg = near_sdk::serde_json::json!({ "token_out": liquidate_into }).to_string();
+        ext_ft_core::ext(asset_id.clone())
+            .with_attached_deposit(NearToken::from_yoctonear(1))
+            .with_static_gas(GAS_FOR_FT_TRANSFER_CALL)
+            .ft_transfer_call(dex_id, amount, None, msg)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_LIQUIDATION)
+                    .on_liquidate(asset_id, amount),
+            )
+    }
 
-        let updates = vec![
-            AssetWeight {
-                weight: U64(6000),
-                asset_address: asset1.clone(),
-            },
-            AssetWeight {
-                weight: U64(4000),
-                asset_address: asset2.clone(),
-            },
-        ];
+    #[private]
+    pub fn on_liquidate(
+        &mut self,
+        asset_id: AssetId,
+        amount: U128,
+        #[callback_result] used: Result<U128, PromiseError>,
+    ) {
+        self.liquidation = None;
+        let used = used.map_or(0, |used| used.0.min(amount.0));
+        let holding = self.assets.get(&asset_id);
+        if used < amount.0 {
+            log!("Liquidation of {} incomplete, keeping the asset", asset_id);
+            // the refund must land on the books even if the holding went away meanwhile
+            let mut holding = holding.unwrap_or_else(|| {
+                log!("{} is missing, re-adding it for the refund", asset_id);
+                let mut holding = Self::new_holding(U64(0));
+                holding.deposits_enabled = false;
+                holding
+            });
+            holding.balance = U128(holding.balance.0 + amount.0 - used);
+            self.assets.insert(&asset_id, &holding);
+            return;
+        }
 
-        contract.update_weights(updates);
+        if holding.is_none() {
+            log!("{} was already removed", asset_id);
+            return;
+        }
+        self.assets.remove(&asset_id);
+        IndexFundEvent::AssetRemoved {
+            asset_address: asset_id,
+        }
+        .emit(&self.event_standard, &self.event_version);
+    }
 
-        let weights = contract.get_weights();
-        assert_eq!(weights.len(), 2);
+    /// Moves a holding to the new contract of a migrated token, keeping every field. Parked
+    /// proportional deposits stay under the old id and can still be withdrawn from there; queued
+    /// redemptions are paid out from the new contract.
+    pub fn migrate_asset(&mut self, old_id: AssetId, new_id: AssetId) {
+        self.assert_owner();
+        self.assert_no_liquidation();
+        require!(
+            !self.is_asset(new_id.clone()),
+            "New asset id already exists"
+        );
+        let holding = self.expect_asset(&old_id);
 
-        let asset1_weight = weights
-            .iter()
-            .find(|w| w.asset_address == asset1)
-            .expect("Asset1 not found");
-        assert_eq!(asset1_weight.weight, U64(6000));
+        self.assets.remove(&old_id);
+        self.assets.insert(&new_id, &holding);
+        if let Some(glide) = self.weight_glide.as_mut() {
+            for weight in glide.from.iter_mut() {
+                if weight.asset_address == old_id {
+                    weight.asset_address = new_id.clone();
+                }
+            }
+        }
+        for index in self.redemption_queue_head..self.redemption_queue.len() {
+            let mut request = self.redemption_queue.get(index).unwrap();
+            if request
+                .amounts
+                .iter()
+                .any(|(asset_id, _)| *asset_id == old_id)
+            {
+                for (asset_id, _) in request.amounts.iter_mut() {
+                    if *asset_id == old_id {
+                        *asset_id = new_id.clone();
+                    }
+                }
+                self.redemption_queue.replace(index, &request);
+            }
+        }
+        IndexFundEvent::AssetMigrated {
+            old_address: old_id,
+            new_address: new_id,
+        }
+        .emit(&self.event_standard, &self.event_version);
+    }
 
-        let asset2_weight = weights
-            .iter()
-            .find(|w| w.asset_address == asset2)
-            .expect("Asset2 not found");
-        assert_eq!(asset2_weight.weight, U64(4000));
+    pub fn set_dex(&mut self, dex_id: AccountId) {
+        self.assert_curator();
+        self.dex_id = Some(dex_id);
     }
 
-    #[test]
-    #[should_panic(expected = "curator not registered")]
-    fn test_update_weights_without_curator() {
-        let mut contract = IndexFund::default();
-        let asset = AccountId::from_str("asset.near").unwrap();
+    pub fn set_wrap_near(&mut self, wrap_near_id: AccountId) {
+        self.assert_curator();
+        self.wrap_near_id = Some(wrap_near_id);
+    }
 
-        contract.update_weights(vec![AssetWeight {
-            weight: U64(10000),
-            asset_address: asset,
-        }]);
+    pub fn set_delegate(&mut self, delegate: Option<AccountId>, max_delta_bps: U64) {
+        self.assert_curator();
+        self.delegate = delegate;
+        self.delegate_max_delta_bps = max_delta_bps;
     }
 
-    #[test]
-    #[should_panic(expected = "Unauthorized")]
-    fn test_update_weights_unauthorized() {
-        let curator = AccountId::from_str("curator.near").unwrap();
-        let unauthorized = AccountId::from_str("unauthorized.near").unwrap();
-        let asset = AccountId::from_str("asset.near").unwrap();
+    pub fn pause(&mut self) {
+        self.assert_curator();
+        require!(!self.paused, "Contract is already paused");
+        self.paused = true;
+        self.record_action(ActionKind::Pause);
+        IndexFundEvent::Paused {
+            by: env::predecessor_account_id(),
+        }
+        .emit(&self.event_standard, &self.event_version);
+    }
 
-        let context = get_context(unauthorized);
-        testing_env!(context.build());
+    /// Starts an orderly shutdown. Deposits are refused from now on while redemptions stay open
+    /// (share lockups no longer apply); once every share is redeemed the fund can be closed.
+    pub fn initiate_wind_down(&mut self) {
+        self.assert_owner();
+        require!(!self.winding_down, "Fund is already winding down");
+        self.winding_down = true;
+        IndexFundEvent::WindDownInitiated {
+            by: env::predecessor_account_id(),
+        }
+        .emit(&self.event_standard, &self.event_version);
+    }
 
-        let mut contract = IndexFund::default();
-        contract.curator_address = Some(curator);
+    /// Closes a wound-down fund with no shares left by pausing it. Deposits stay refused even if
+    /// it's unpaused later.
+    pub fn close_fund(&mut self) {
+        self.assert_owner();
+        require!(self.winding_down, "Fund is not winding down");
+        require!(self.total_shares.0 == 0, "Shares are still outstanding");
+        self.paused = true;
+        IndexFundEvent::FundClosed {
+            by: env::predecessor_account_id(),
+        }
+        .emit(&self.event_standard, &self.event_version);
+    }
 
-        contract.update_weights(vec![AssetWeight {
-            weight: U64(10000),
-            asset_address: asset,
-        }]);
+    pub fn unpause(&mut self) {
+        self.assert_curator();
+        require!(self.paused, "Contract is not paused");
+        self.paused = false;
+        self.unpaused_at = U64(env::block_timestamp());
+        self.record_action(ActionKind::Unpause);
+        IndexFundEvent::Unpaused {
+            by: env::predecessor_account_id(),
+        }
+        .emit(&self.event_standard, &self.event_version);
     }
 
-    #[test]
-    #[should_panic(expected = "Final weights must sum to 100%")]
-    fn test_update_weights_invalid_sum() {
-        let curator = AccountId::from_str("curator.near").unwrap();
-        let asset = AccountId::from_str("asset.near").unwrap();
+    /// Permanently gives up ownership, disabling every owner-only method. `confirmation` must be
+    /// this contract's account id so it can't happen by accident.
+    pub fn renounce_ownership(&mut self, confirmation: String) {
+        self.assert_owner();
+        require!(
+            confirmation == env::current_account_id().as_str(),
+            "Confirmation must be the contract account id"
+        );
 
-        let context = get_context(curator.clone());
-        testing_env!(context.build());
+        let previous_owner = self.owner_id.take().expect("owner not set");
+        IndexFundEvent::OwnershipRenounced { previous_owner }
+            .emit(&self.event_standard, &self.event_version);
+    }
 
-        let mut contract = IndexFund::default();
-        contract.curator_address = Some(curator);
+    /// Announces an upgrade to the code with `code_hash`, deployable from `eta` on. Replaces any
+    /// earlier proposal.
+    pub fn propose_upgrade(&mut self, code_hash: Base58CryptoHash, eta: U64) {
+        self.assert_owner();
+        require!(
+            eta.0 >= env::block_timestamp() + MIN_UPGRADE_DELAY_NS,
+            "Upgrade eta is too soon"
+        );
+        self.proposed_upgrade = Some(UpgradeProposal { code_hash, eta });
+        IndexFundEvent::UpgradeProposed { code_hash, eta }
+            .emit(&self.event_standard, &self.event_version);
+    }
 
+    /// Deploys the proposed code once its eta has passed and runs the new code's `migrate` in the
+    /// same batch, so a migration failure also reverts the deploy. Takes the raw wasm as borsh
+    /// input.
+    pub fn do_upgrade(&mut self, #[serializer(borsh)] code: Vec<u8>) -> Promise {
+        self.assert_owner();
+        let proposal = self.proposed_upgrade.take().expect("No upgrade proposed");
+        require!(
+            env::block_timestamp() >= proposal.eta.0,
+            "Upgrade timelock has not elapsed"
+        );
+        require!(
+            Base58CryptoHash::from(env::sha256_array(&code)) == proposal.code_hash,
+            "Code doesn't match the proposed upgrade"
+        );
+        Promise::new(env::current_account_id())
+            .deploy_contract(code)
+            .function_call_weight(
+                "migrate".to_string(),
+                vec![],
+                NearToken::from_yoctonear(0),
+                GAS_FOR_MIGRATE,
+                GasWeight(1),
+            )
+    }
+
+    /// Called by `do_upgrade` right after the deploy. Every version must convert the previous
+    /// version's state layout here; this one reads its own layout unchanged.
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        env::state_read().expect("No state to migrate")
+    }
+
+    // Re-anchors the rebalance interval, e.g. after a migration or a long pause.
+    // `block_height` is a timestamp in ms when the interval is measured in milliseconds.
+    pub fn set_last_rebalance(&mut self, block_height: U64) {
+        self.assert_owner();
+        require!(
+            block_height.0 <= self.interval_now(),
+            "Last rebalance can't be in the future"
+        );
+        let old = self.last_rebalance;
+        self.last_rebalance = block_height;
+        IndexFundEvent::LastRebalanceSet {
+            old,
+            new: block_height,
+        }
+        .emit(&self.event_standard, &self.event_version);
+    }
+
+    // The recipient is registered for shares so fees can always be minted to it
+    pub fn set_fee_recipient(&mut self, fee_recipient: AccountId) {
+        self.assert_owner();
+        if self.shares.get(&fee_recipient).is_none() {
+            self.shares.insert(&fee_recipient, &U128(0));
+        }
+        self.fee_recipient = Some(fee_recipient);
+    }
+
+    pub fn set_entry_fee_bps(&mut self, entry_fee_bps: U64) {
+        self.assert_owner();
+        require!(entry_fee_bps.0 < 10000, "Invalid fee");
+        require!(
+            entry_fee_bps.0 == 0 || self.fee_recipient.is_some(),
+            "fee recipient not set"
+        );
+        self.entry_fee_bps = entry_fee_bps;
+    }
+
+    pub fn set_zero_supply_policy(&mut self, policy: ZeroSupplyPolicy) {
+        self.assert_owner();
+        require!(
+            policy == ZeroSupplyPolicy::CleanMint || self.fee_recipient.is_some(),
+            "fee recipient not set"
+        );
+        self.zero_supply_policy = policy;
+    }
+
+    pub fn set_keeper_reward_bps(&mut self, keeper_reward_bps: U64) {
+        self.assert_owner();
+        require!(
+            keeper_reward_bps.0 <= MAX_KEEPER_REWARD_BPS,
+            "Invalid reward"
+        );
+        self.keeper_reward_bps = keeper_reward_bps;
+    }
+
+    pub fn set_exit_fee_bps(&mut self, exit_fee_bps: U64) {
+        self.assert_owner();
+        require!(exit_fee_bps.0 < 10000, "Invalid fee");
+        self.exit_fee_bps = exit_fee_bps;
+    }
+
+    // Only before the first shares are minted, so existing balances keep their meaning
+    pub fn set_share_decimals(&mut self, share_decimals: u8) {
+        self.assert_owner();
+        require!(
+            share_decimals <= SHARE_BASE_DECIMALS,
+            "Invalid share decimals"
+        );
+        require!(
+            self.total_shares.0 == 0,
+            "Can't change decimals with outstanding shares"
+        );
+        self.share_decimals = share_decimals;
+    }
+
+    pub fn set_unpause_grace_ns(&mut self, unpause_grace_ns: U64) {
+        self.assert_owner();
+        self.unpause_grace_ns = unpause_grace_ns;
+    }
+
+    pub fn set_share_lockup_ns(&mut self, share_lockup_ns: U64) {
+        self.assert_owner();
+        self.share_lockup_ns = share_lockup_ns;
+    }
+
+    // Fees owed at the old rate are taken before the new one applies
+    pub fn set_management_fee_bps(&mut self, management_fee_bps: U64) {
+        self.assert_owner();
+        require!(management_fee_bps.0 < 10000, "Invalid fee");
+        require!(
+            management_fee_bps.0 == 0 || self.fee_recipient.is_some(),
+            "fee recipient not set"
+        );
+        self.internal_accrue_management_fee();
+        self.management_fee_bps = management_fee_bps;
+    }
+
+    /// Takes the management fee owed since the last accrual. Anyone may call this. Returns the
+    /// shares minted to the fee recipient.
+    pub fn accrue_management_fee(&mut self) -> U128 {
+        U128(self.internal_accrue_management_fee())
+    }
+
+    // Sets target weights proportional to the stored market caps; uncapped assets get zero
+    pub fn rebalance_weights_by_market_cap(&mut self) {
+        self.assert_curator();
+        self.assert_active();
+
+        let mut caps: Vec<(AssetId, u128)> = self
+            .assets
+            .iter()
+            .map(|(asset_id, holding)| (asset_id, holding.market_cap.0))
+            .collect();
+        caps.sort();
+        require!(
+            caps.iter().any(|(_, cap)| *cap > 0),
+            "No market caps reported"
+        );
+
+        let weights: Vec<AssetWeight> = Self::normalize_weights(&caps)
+            .into_iter()
+            .map(|(asset_address, weight)| AssetWeight {
+                weight: U64(weight),
+                asset_address,
+            })
+            .collect();
+        self.internal_update_weights(&weights);
+    }
+
+    // Adopts the current value allocation as the new target weights
+    pub fn set_weights_from_balances(&mut self) {
+        self.assert_curator();
+        self.assert_active();
+
+        let values: Vec<(AssetId, u128)> = self
+            .assets
+            .iter()
+            .map(|(asset_id, holding)| (asset_id, Self::asset_value(&holding)))
+            .collect();
+        require!(values.iter().any(|(_, v)| *v > 0), "Fund has no value");
+
+        let weights: Vec<AssetWeight> = Self::normalize_weights(&values)
+            .into_iter()
+            .map(|(asset_address, weight)| AssetWeight {
+                weight: U64(weight),
+                asset_address,
+            })
+            .collect();
+        self.internal_update_weights(&weights);
+    }
+
+    /// Records a rebalance once the interval has elapsed and returns the trades needed to get back
+    /// to target weights: quote-asset value to buy (+) or sell (-) per asset. The trades
+    /// themselves are executed off-chain.
+    pub fn rebalance(&mut self) -> Vec<(AssetId, i128)> {
+        let caller = env::predecessor_account_id();
+        require!(
+            self.curator_address.as_ref() == Some(&caller)
+                || self.keeper_id.as_ref() == Some(&caller),
+            "Unauthorized"
+        );
+        self.assert_active();
+        require!(
+            self.interval_now() >= self.last_rebalance.0 + self.rebalance_interval.0,
+            "Rebalance interval has not elapsed"
+        );
+        // targets are undefined without weights; only reachable through a bad migration
+        require!(
+            self.assets.values().any(|h| h.weight.0 > 0),
+            "Can't rebalance with zero total weight"
+        );
+
+        let needed = self.internal_max_drift_bps() > self.drift_tolerance_bps.0;
+        let trades = self.internal_rebalance_plan();
+        if self
+            .weight_glide
+            .as_ref()
+            .is_some_and(|glide| env::block_height() >= glide.start_block.0 + glide.glide_blocks.0)
+        {
+            self.weight_glide = None;
+        }
+        self.last_rebalance = U64(self.interval_now());
+        self.rebalance_count = U64(self.rebalance_count.0 + 1);
+        if needed {
+            self.reward_keeper(&caller);
+        }
+
+        env::log_str(&format!("Rebalanced: {:?}", trades));
+        trades
+    }
+
+    /// Sets the rebalance interval, in the current interval unit. It can't go below
+    /// MIN_REBALANCE_INTERVAL_BLOCKS (or its equivalent in milliseconds).
+    pub fn set_rebalance_interval(&mut self, rebalance_interval: U64) {
+        self.assert_curator();
+        let floor = match self.interval_unit {
+            IntervalUnit::Blocks => MIN_REBALANCE_INTERVAL_BLOCKS,
+            IntervalUnit::Milliseconds => MIN_REBALANCE_INTERVAL_BLOCKS * MS_PER_BLOCK,
+        };
+        require!(
+            rebalance_interval.0 >= floor,
+            "Rebalance interval below the minimum"
+        );
+        self.rebalance_interval = rebalance_interval;
+    }
+
+    /// Switches the rebalance interval between blocks and milliseconds. The interval is converted
+    /// at MS_PER_BLOCK, and `last_rebalance` is re-anchored so the same fraction of the interval
+    /// has elapsed in the new unit as in the old one.
+    pub fn set_interval_unit(&mut self, unit: IntervalUnit) {
+        self.assert_owner();
+        if unit == self.interval_unit {
+            return;
+        }
+
+        let old_interval = self.rebalance_interval.0;
+        let elapsed = self.interval_now().saturating_sub(self.last_rebalance.0);
+        let new_interval = match unit {
+            IntervalUnit::Milliseconds => old_interval * MS_PER_BLOCK,
+            IntervalUnit::Blocks => (old_interval / MS_PER_BLOCK).max(1),
+        };
+        // u128 keeps `elapsed * new_interval` from overflowing for long-idle funds
+        let new_elapsed = (elapsed as u128 * new_interval as u128 / old_interval as u128) as u64;
+
+        self.interval_unit = unit;
+        self.rebalance_interval = U64(new_interval);
+        self.last_rebalance = U64(self.interval_now().saturating_sub(new_elapsed));
+    }
+
+    pub fn set_auto_prune_zero_weight(&mut self, enabled: bool) {
+        self.assert_curator();
+        self.auto_prune_zero_weight = enabled;
+    }
+
+    pub fn set_strict_weight_zeroing(&mut self, strict: bool) {
+        self.assert_curator();
+        self.strict_weight_zeroing = strict;
+    }
+
+    pub fn set_weight_sum_tolerance_bps(&mut self, tolerance_bps: U64) {
+        self.assert_curator();
+        require!(tolerance_bps.0 < WEIGHT_DENOMINATOR, "Invalid tolerance");
+        self.weight_sum_tolerance_bps = tolerance_bps;
+    }
+
+    pub fn set_ordering_seed(&mut self, ordering_seed: U64) {
+        self.assert_curator();
+        self.ordering_seed = ordering_seed;
+    }
+
+    pub fn set_drift_tolerance_bps(&mut self, drift_tolerance_bps: U64) {
+        self.assert_curator();
+        self.drift_tolerance_bps = drift_tolerance_bps;
+    }
+
+    pub fn set_rebalance_strategy(&mut self, strategy: RebalanceStrategy) {
+        self.assert_curator();
+        self.rebalance_strategy = strategy;
+    }
+
+    pub fn set_rebalance_mode(&mut self, mode: RebalanceMode) {
+        self.assert_curator();
+        if let RebalanceMode::Band { width_bps } = mode {
+            require!(width_bps.0 < WEIGHT_DENOMINATOR, "Invalid band width");
+        }
+        self.rebalance_mode = mode;
+    }
+
+    pub fn set_keeper(&mut self, keeper_id: AccountId) {
+        self.assert_owner();
+        self.keeper_id = Some(keeper_id);
+    }
+
+    // Records the current NAV; keepers call this on a schedule to build a performance history
+    pub fn snapshot_nav(&mut self) -> NavSnapshot {
+        let caller = env::predecessor_account_id();
+        require!(
+            self.owner_id.as_ref() == Some(&caller) || self.keeper_id.as_ref() == Some(&caller),
+            "Unauthorized"
+        );
+
+        let snapshot = NavSnapshot {
+            block_height: U64(env::block_height()),
+            timestamp: U64(env::block_timestamp()),
+            nav: U128(self.internal_nav()),
+            nav_per_share: U128(self.internal_nav_per_share()),
+        };
+        if self.nav_history.len() < MAX_NAV_HISTORY {
+            self.nav_history.push(&snapshot);
+        } else {
+            self.nav_history.replace(self.nav_history_start, &snapshot);
+            self.nav_history_start = (self.nav_history_start + 1) % MAX_NAV_HISTORY;
+        }
+        snapshot
+    }
+
+    pub fn set_min_deposit_value(&mut self, min_deposit_value: U128) {
+        self.assert_owner();
+        self.min_deposit_value = min_deposit_value;
+    }
+
+    pub fn set_max_share_supply(&mut self, max_share_supply: Option<U128>) {
+        self.assert_owner();
+        self.max_share_supply = max_share_supply;
+    }
+
+    pub fn set_oracle(&mut self, oracle_address: AccountId) {
+        self.assert_curator();
+        self.oracle_address = Some(oracle_address);
+    }
+
+    pub fn set_max_price_age_ns(&mut self, max_price_age_ns: U64) {
+        self.assert_curator();
+        self.max_price_age_ns = max_price_age_ns;
+    }
+
+    // Two-step rotation: the current oracle nominates its successor, who must accept
+    pub fn propose_oracle(&mut self, new_oracle: AccountId) {
+        self.assert_oracle();
+        self.proposed_oracle = Some(new_oracle);
+    }
+
+    pub fn accept_oracle(&mut self) {
+        let caller = env::predecessor_account_id();
+        require!(
+            self.proposed_oracle.as_ref() == Some(&caller),
+            "Unauthorized"
+        );
+        self.proposed_oracle = None;
+        self.oracle_address = Some(caller);
+    }
+
+    pub fn set_asset_oracle(&mut self, asset_id: AssetId, oracle: Option<AccountId>) {
+        self.assert_curator();
+        let mut holding = self.expect_asset(&asset_id);
+        holding.oracle = oracle;
+        self.assets.insert(&asset_id, &holding);
+    }
+
+    pub fn set_max_price_move_bps(&mut self, max_price_move_bps: U64) {
+        self.assert_curator();
+        self.max_price_move_bps = max_price_move_bps;
+    }
+
+    pub fn set_prices_frozen(&mut self, frozen: bool) {
+        self.assert_owner();
+        self.prices_frozen = frozen;
+    }
+
+    /// Each price must come from the asset's own oracle, or the fund's oracle if it has none.
+    pub fn update_prices(&mut self, prices: Vec<(AssetId, Price)>) {
+        self.assert_prices_live();
+        // every price is authorized on its own, so an empty update would be unauthenticated
+        require!(!prices.is_empty(), "No prices");
+        for (asset_id, price) in prices.iter() {
+            self.assert_price_source(asset_id);
+            self.internal_set_price(asset_id, *price, U64(env::block_timestamp()));
+        }
+        self.record_action(ActionKind::UpdatePrices);
+
+        env::log_str(&format!("Updated prices: {:?}", prices));
+    }
+
+    pub fn update_market_caps(&mut self, market_caps: Vec<(AssetId, U128)>) {
+        self.assert_oracle();
+        for (asset_id, market_cap) in market_caps.iter() {
+            let mut holding = self.expect_asset(asset_id);
+            holding.market_cap = *market_cap;
+            self.assets.insert(asset_id, &holding);
+        }
+    }
+
+    // Like `update_prices`, but with the oracle's own observation time (ns) for every price
+    pub fn update_prices_ts(&mut self, prices: Vec<(AssetId, Price, U64)>) {
+        self.assert_prices_live();
+        require!(!prices.is_empty(), "No prices");
+        let now = env::block_timestamp();
+        for (asset_id, price, timestamp) in prices.iter() {
+            self.assert_price_source(asset_id);
+            require!(
+                timestamp.0 <= now,
+                format!("Price timestamp in the future for {}", asset_id)
+            );
+            let last_updated = self.expect_asset(asset_id).last_updated;
+            require!(
+                *timestamp >= last_updated,
+                format!("Price older than the current one for {}", asset_id)
+            );
+            self.internal_set_price(asset_id, *price, *timestamp);
+        }
+        self.record_action(ActionKind::UpdatePrices);
+
+        env::log_str(&format!("Updated prices: {:?}", prices));
+    }
+
+    pub fn set_tradeable_after(&mut self, asset_id: AssetId, tradeable_after_ns: U64) {
+        self.assert_curator();
+        let mut holding = self.expect_asset(&asset_id);
+        holding.tradeable_after_ns = tradeable_after_ns;
+        self.assets.insert(&asset_id, &holding);
+    }
+
+    // Decimals can only change while the asset holds nothing, so NAV never jumps
+    pub fn set_asset_decimals(&mut self, asset_id: AssetId, decimals: u8) {
+        self.assert_curator();
+        let mut holding = self.expect_asset(&asset_id);
+        require!(
+            holding.balance.0 == 0,
+            "Can't change decimals of a funded asset"
+        );
+        holding.decimals = decimals;
+        self.assets.insert(&asset_id, &holding);
+    }
+
+    // Caps apply to future deposits only; a balance already above the cap is left alone
+    pub fn set_asset_max_balance(&mut self, asset_id: AssetId, max_balance: Option<U128>) {
+        self.assert_curator();
+        let mut holding = self.expect_asset(&asset_id);
+        holding.max_balance = max_balance;
+        self.assets.insert(&asset_id, &holding);
+    }
+
+    // The current target weight must already lie within the new bounds
+    pub fn set_asset_weight_bounds(&mut self, asset_id: AssetId, min_weight: U64, max_weight: U64) {
+        self.assert_curator();
+        require!(
+            min_weight <= max_weight && max_weight.0 <= WEIGHT_DENOMINATOR,
+            "Invalid weight bounds"
+        );
+        let mut holding = self.expect_asset(&asset_id);
+        require!(
+            min_weight <= holding.weight && holding.weight <= max_weight,
+            format!("Weight out of bounds for {}", asset_id)
+        );
+        holding.min_weight = min_weight;
+        holding.max_weight = max_weight;
+        self.assets.insert(&asset_id, &holding);
+    }
+
+    pub fn set_reporter(&mut self, reporter: Option<AccountId>) {
+        self.assert_owner();
+        self.reporter = reporter;
+    }
+
+    pub fn set_fund_metadata(&mut self, metadata: FundMetadata) {
+        if !self.is_reporter() {
+            self.assert_owner();
+        }
+        self.metadata = Some(metadata);
+    }
+
+    pub fn set_asset_label(&mut self, asset_id: AssetId, label: Option<String>) {
+        if !self.is_reporter() {
+            self.assert_curator();
+        }
+        if let Some(label) = label.as_ref() {
+            require!(label.len() <= MAX_LABEL_LEN, "Label too long");
+        }
+        let mut holding = self.expect_asset(&asset_id);
+        holding.label = label;
+        self.assets.insert(&asset_id, &holding);
+    }
+
+    pub fn set_asset_deposits_enabled(&mut self, asset_id: AssetId, enabled: bool) {
+        self.assert_curator();
+        let mut holding = self.expect_asset(&asset_id);
+        holding.deposits_enabled = enabled;
+        self.assets.insert(&asset_id, &holding);
+    }
+
+    pub fn set_asset_fee_on_transfer(&mut self, asset_id: AssetId, fee_on_transfer: bool) {
+        self.assert_curator();
+        let mut holding = self.expect_asset(&asset_id);
+        holding.fee_on_transfer = fee_on_transfer;
+        self.assets.insert(&asset_id, &holding);
+    }
+
+    /// Deposits previously parked tokens in proportion to the current portfolio (or to the target
+    /// weights for an empty fund). Every asset must be supplied within the tolerance.
+    pub fn deposit_proportional(&mut self, amounts: Vec<(AssetId, U128)>) -> U128 {
+        self.assert_active();
+        self.assert_not_winding_down();
+        self.assert_no_liquidation();
+        let account_id = env::predecessor_account_id();
+        self.internal_accrue_management_fee();
+
+        let mut deposit_values: std::collections::HashMap<AssetId, u128> =
+            std::collections::HashMap::new();
+        for (asset_id, amount) in amounts.iter() {
+            let holding = self.expect_asset(asset_id);
+            require!(holding.last_price.0 > 0, "Asset has no price");
+            *deposit_values.entry(asset_id.clone()).or_default() +=
+                Self::value_of(&holding, amount.0);
+        }
+        let deposit_value: u128 = deposit_values.values().sum();
+        require!(deposit_value > 0, "Nothing to deposit");
+        require!(
+            deposit_value >= self.min_deposit_value.0,
+            "Deposit below the minimum"
+        );
+
+        // Compare each asset's share of the deposit with its share of the portfolio
+        let nav = self.internal_nav();
+        for (asset_id, holding) in self.assets.iter() {
+            let expected_bps = (Self::asset_value(&holding) * 10000)
+                .checked_div(nav)
+                .unwrap_or(u128::from(holding.weight.0));
+            let actual_bps =
+                deposit_values.get(&asset_id).copied().unwrap_or(0) * 10000 / deposit_value;
+            require!(
+                expected_bps.abs_diff(actual_bps) <= PROPORTIONAL_DEPOSIT_TOLERANCE_BPS,
+                "Deposit proportions don't match the portfolio"
+            );
+        }
+
+        self.settle_orphaned_value();
+        let minted = self.shares_for_value(deposit_value);
+        let minted = self.mint_deposit_shares(&account_id, minted, deposit_value);
+        for (asset_id, amount) in amounts.iter() {
+            let key = (account_id.clone(), asset_id.clone());
+            let pending = self.pending_deposits.get(&key).unwrap_or(U128(0));
+            require!(pending.0 >= amount.0, "Insufficient pending deposit");
+            if pending.0 == amount.0 {
+                self.pending_deposits.remove(&key);
+            } else {
+                self.pending_deposits
+                    .insert(&key, &U128(pending.0 - amount.0));
+            }
+
+            let mut holding = self.expect_asset(asset_id);
+            require!(
+                amount.0 <= Self::room_under_cap(&holding),
+                format!("Deposit would exceed the balance cap for {}", asset_id)
+            );
+            holding.balance = U128(holding.balance.0 + amount.0);
+            self.assets.insert(asset_id, &holding);
+        }
+
+        log!("Proportional deposit minted {} shares", minted);
+        U128(minted)
+    }
+
+    /// Deposits the attached NEAR. It's wrapped with the wrapped-NEAR contract first, and shares
+    /// are minted against the wrapped-NEAR holding once the wrap succeeds; if the wrap fails
+    /// the NEAR is refunded.
+    #[payable]
+    pub fn deposit(&mut self) -> Promise {
+        let account_id = env::predecessor_account_id();
+        let wrap_near_id = self.wrap_near_id.clone().expect("Wrapped NEAR not set");
+        let amount = env::attached_deposit().as_yoctonear();
+        require!(amount > 0, "Nothing to deposit");
+        self.internal_accrue_management_fee();
+        if let Some(error) = self.wrapped_near_deposit_error(&account_id, &wrap_near_id, amount) {
+            env::panic_str(&error);
+        }
+
+        ext_wrap_near::ext(wrap_near_id.clone())
+            .with_attached_deposit(NearToken::from_yoctonear(amount))
+            .with_static_gas(GAS_FOR_NEAR_DEPOSIT)
+            .near_deposit()
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_WRAP)
+                    .on_near_wrapped(account_id, wrap_near_id, U128(amount)),
+            )
+    }
+
+    // Credits the wrapped NEAR, unless something changed since `deposit` checked: then the
+    // wNEAR goes back to the depositor (or stays a pending deposit if that transfer fails)
+    #[private]
+    pub fn on_near_wrapped(
+        &mut self,
+        account_id: AccountId,
+        wrap_near_id: AccountId,
+        amount: U128,
+        #[callback_result] result: Result<(), PromiseError>,
+    ) -> U128 {
+        if result.is_err() {
+            log!("Wrapping NEAR failed, refunding");
+            Promise::new(account_id).transfer(NearToken::from_yoctonear(amount.0));
+            return U128(0);
+        }
+
+        // the deposit mints against the supply after the fee, so the checks must see it too
+        self.internal_accrue_management_fee();
+        if let Some(error) = self.wrapped_near_deposit_error(&account_id, &wrap_near_id, amount.0) {
+            log!("{}, refunding the wrapped NEAR", error);
+            ext_ft_core::ext(wrap_near_id.clone())
+                .with_attached_deposit(NearToken::from_yoctonear(1))
+                .with_static_gas(GAS_FOR_FT_TRANSFER)
+                .ft_transfer(account_id.clone(), amount, None)
+                .then(
+                    Self::ext(env::current_account_id())
+                        .with_static_gas(GAS_FOR_RESOLVE_TRANSFER)
+                        .on_pending_withdraw(account_id, wrap_near_id, amount),
+                );
+            return U128(0);
+        }
+
+        let minted = self.internal_deposit(&account_id, &wrap_near_id, amount.0);
+        log!("Deposited {} yoctoNEAR, minted {} shares", amount.0, minted);
+        U128(minted)
+    }
+
+    /// Returns parked tokens that were never deposited.
+    #[payable]
+    pub fn withdraw_pending_deposit(&mut self, asset_id: AssetId) -> Promise {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let amount = self
+            .pending_deposits
+            .remove(&(account_id.clone(), asset_id.clone()))
+            .expect("No pending deposit");
+
+        ext_ft_core::ext(asset_id.clone())
+            .with_attached_deposit(NearToken::from_yoctonear(1))
+            .with_static_gas(GAS_FOR_FT_TRANSFER)
+            .ft_transfer(account_id.clone(), amount, None)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_TRANSFER)
+                    .on_pending_withdraw(account_id, asset_id, amount),
+            )
+    }
+
+    #[private]
+    pub fn on_pending_withdraw(
+        &mut self,
+        account_id: AccountId,
+        asset_id: AssetId,
+        amount: U128,
+        #[callback_result] result: Result<(), PromiseError>,
+    ) {
+        if result.is_err() {
+            log!(
+                "Withdrawal of {} failed, restoring pending deposit",
+                asset_id
+            );
+            let key = (account_id, asset_id);
+            let pending = self.pending_deposits.get(&key).unwrap_or(U128(0));
+            self.pending_deposits
+                .insert(&key, &U128(pending.0 + amount.0));
+        }
+    }
+
+    /// NEP-141 style share transfer to a storage-registered account. Shares still within the
+    /// sender's deposit lockup can't move, so the lockup can't be dodged by transferring.
+    #[payable]
+    pub fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>) {
+        assert_one_yocto();
+        let sender_id = env::predecessor_account_id();
+        require!(
+            sender_id != receiver_id,
+            "Sender and receiver should be different"
+        );
+        require!(amount.0 > 0, "The amount should be a positive number");
+        self.assert_unlocked(&sender_id);
+
+        self.internal_burn_shares(&sender_id, amount.0);
+        self.internal_mint_shares(&receiver_id, amount.0);
+        FtTransfer {
+            old_owner_id: &sender_id,
+            new_owner_id: &receiver_id,
+            amount,
+            memo: memo.as_deref(),
+        }
+        .emit();
+    }
+
+    pub fn ft_balance_of(&self, account_id: AccountId) -> U128 {
+        self.shares.get(&account_id).unwrap_or(U128(0))
+    }
+
+    pub fn ft_total_supply(&self) -> U128 {
+        self.total_shares
+    }
+
+    /// Incident response: moves `amount` of an asset out of the fund to `recipient`, e.g. a safe
+    /// multisig. Owner only, and only while paused.
+    #[payable]
+    pub fn emergency_withdraw(
+        &mut self,
+        asset_id: AssetId,
+        amount: U128,
+        recipient: AccountId,
+    ) -> Promise {
+        assert_one_yocto();
+        self.assert_owner();
+        require!(self.paused, "Contract is not paused");
+
+        let mut holding = self.expect_asset(&asset_id);
+        require!(holding.balance >= amount, "Insufficient balance");
+        holding.balance = U128(holding.balance.0 - amount.0);
+        self.assets.insert(&asset_id, &holding);
+
+        IndexFundEvent::EmergencyWithdraw {
+            by: env::predecessor_account_id(),
+            asset_address: asset_id.clone(),
+            amount,
+            recipient: recipient.clone(),
+        }
+        .emit(&self.event_standard, &self.event_version);
+        self.transfer_asset(&asset_id, &recipient, amount.0)
+    }
+
+    /// State correction for migrations: overwrites existing holdings wholesale. Owner only, and
+    /// only while paused; the resulting weights must still sum to 100%.
+    pub fn admin_set_holdings(&mut self, holdings: Vec<(AssetId, AssetHolding)>) {
+        self.assert_owner();
+        require!(self.paused, "Contract is not paused");
+        self.assert_no_liquidation();
+
+        for (asset_id, holding) in holdings {
+            self.expect_asset(&asset_id);
+            IndexFundEvent::HoldingOverwritten {
+                by: env::predecessor_account_id(),
+                asset_address: asset_id.clone(),
+                balance: holding.balance,
+                weight: holding.weight,
+                last_price: holding.last_price,
+            }
+            .emit(&self.event_standard, &self.event_version);
+            self.assets.insert(&asset_id, &holding);
+        }
+
+        let total_weight: u64 = self.assets.values().map(|h| h.weight.0).sum();
+        require!(
+            total_weight.abs_diff(WEIGHT_DENOMINATOR) <= self.weight_sum_tolerance_bps.0,
+            "Final weights must sum to 100%"
+        );
+        self.weight_glide = None;
+    }
+
+    /// Burns `shares` and transfers the caller their pro rata part of every asset, less the exit
+    /// fee which stays in the fund. Returns the amounts sent per asset.
+    #[payable]
+    pub fn redeem(&mut self, shares: U128) -> Vec<(AssetId, U128)> {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+
+        let payouts = self.internal_redeem(&account_id, shares.0);
+        for (asset_id, amount) in payouts.iter() {
+            self.transfer_redemption(&account_id, asset_id, *amount);
+        }
+
+        log!("Redeemed {} shares for {:?}", shares.0, payouts);
+        payouts
+    }
+
+    /// Burns `shares` now and queues the payout until `process_redemptions` delivers it, for when
+    /// assets can't be sent out straight away. The queued amounts leave the fund's books at once.
+    #[payable]
+    pub fn request_redemption(&mut self, shares: U128) -> u64 {
+        assert_one_yocto();
+        let account = env::predecessor_account_id();
+
+        let amounts = self.internal_redeem(&account, shares.0);
+        self.redemption_queue.push(&RedemptionRequest {
+            account,
+            amounts,
+            requested_at: U64(env::block_timestamp()),
+        });
+        self.redemption_queue.len() - 1
+    }
+
+    /// Delivers up to `limit` queued redemptions, oldest first. Returns how many were processed.
+    pub fn process_redemptions(&mut self, limit: u64) -> u64 {
+        let mut processed = 0;
+        while processed < limit && self.redemption_queue_head < self.redemption_queue.len() {
+            let request = self
+                .redemption_queue
+                .get(self.redemption_queue_head)
+                .unwrap();
+            for (asset_id, amount) in request.amounts.iter() {
+                self.transfer_redemption(&request.account, asset_id, *amount);
+            }
+            self.redemption_queue_head += 1;
+            processed += 1;
+        }
+
+        // Reclaim storage once everything queued has been handed out
+        if self.redemption_queue_head == self.redemption_queue.len() {
+            self.redemption_queue.clear();
+            self.redemption_queue_head = 0;
+        }
+        processed
+    }
+
+    /// Whether `redeem` would currently accept shares from `account_id`, with the reason if not.
+    pub fn can_redeem(&self, account_id: AccountId) -> (bool, Option<String>) {
+        let reason = if self.paused {
+            Some("Contract is paused")
+        } else if self.shares.get(&account_id).unwrap_or(U128(0)).0 == 0 {
+            Some("No shares")
+        } else if self.is_locked(&account_id) {
+            Some("Shares are locked")
+        } else if self.liquidation.is_some() {
+            Some("A liquidation is in flight")
+        } else {
+            None
+        };
+        (reason.is_none(), reason.map(str::to_string))
+    }
+
+    #[private]
+    pub fn on_redemption_transfer(
+        &mut self,
+        account: AccountId,
+        asset_id: AssetId,
+        amount: U128,
+        #[callback_result] result: Result<(), PromiseError>,
+    ) {
+        if result.is_err() {
+            // the claim is still owed, so put it back at the end of the queue
+            log!(
+                "Transfer of {} {} to {} failed, requeueing",
+                amount.0,
+                asset_id,
+                account
+            );
+            self.redemption_queue.push(&RedemptionRequest {
+                account,
+                amounts: vec![(asset_id, amount)],
+                requested_at: U64(env::block_timestamp()),
+            });
+        }
+    }
+
+    // Queued redemptions that haven't been processed yet, oldest first
+    pub fn get_redemption_queue(&self, from_index: u64, limit: u64) -> Vec<RedemptionRequest> {
+        (self.redemption_queue_head + from_index..self.redemption_queue.len())
+            .take(limit as usize)
+            .filter_map(|index| self.redemption_queue.get(index))
+            .collect()
+    }
+
+    // Quote-asset value, at current prices, of everything still owed to the redemption queue.
+    // These amounts are already excluded from the holdings and NAV.
+    pub fn get_pending_redemption_value(&self) -> U128 {
+        let value = (self.redemption_queue_head..self.redemption_queue.len())
+            .filter_map(|index| self.redemption_queue.get(index))
+            .flat_map(|request| request.amounts)
+            .map(|(asset_id, amount)| {
+                self.assets
+                    .get(&asset_id)
+                    .map_or(0, |holding| Self::value_of(&holding, amount.0))
+            })
+            .sum();
+        U128(value)
+    }
+
+    #[private]
+    pub fn on_asset_transfer(
+        &mut self,
+        asset_id: AssetId,
+        amount: U128,
+        #[callback_result] result: Result<(), PromiseError>,
+    ) {
+        if result.is_err() {
+            // the tokens never left, so keep them on the books
+            log!("Transfer of {} {} failed", amount.0, asset_id);
+            if let Some(mut holding) = self.assets.get(&asset_id) {
+                holding.balance = U128(holding.balance.0 + amount.0);
+                self.assets.insert(&asset_id, &holding);
+            }
+        }
+    }
+
+    pub fn get_pending_deposit(&self, account_id: AccountId, asset_id: AssetId) -> U128 {
+        self.pending_deposits
+            .get(&(account_id, asset_id))
+            .unwrap_or(U128(0))
+    }
+
+    pub fn get_weights(&self) -> Vec<AssetWeight> {
+        self.assets
+            .iter()
+            .map(|(asset_address, h)| AssetWeight {
+                weight: h.weight,
+                asset_address,
+            })
+            .collect()
+    }
+
+    // Paginated dump of every holding for indexers bootstrapping from scratch
+    pub fn export_state(&self, from_index: u64, limit: u64) -> StateChunk {
+        let mut asset_ids: Vec<AssetId> = self.assets.keys().collect();
+        asset_ids.sort();
+
+        let total_assets = asset_ids.len() as u64;
+        let holdings: Vec<(AssetId, AssetHolding)> = asset_ids
+            .into_iter()
+            .skip(from_index as usize)
+            .take(limit as usize)
+            .map(|asset_id| {
+                let holding = self.expect_asset(&asset_id);
+                (asset_id, holding)
+            })
+            .collect();
+        let end = from_index + holdings.len() as u64;
+        StateChunk {
+            holdings,
+            next_index: (end < total_assets).then_some(end),
+            total_assets,
+        }
+    }
+
+    // What weights are expressed in parts of, so clients don't hardcode 10000
+    pub fn get_weight_denominator(&self) -> U64 {
+        U64(WEIGHT_DENOMINATOR)
+    }
+
+    // Weights rebalancing currently targets; the stored weights unless a glide is in progress
+    pub fn get_effective_weights(&self) -> Vec<AssetWeight> {
+        self.effective_holdings()
+            .into_iter()
+            .map(|(asset_address, h)| AssetWeight {
+                weight: h.weight,
+                asset_address,
+            })
+            .collect()
+    }
+
+    /// Exposure a new depositor of `deposit_value` ends up with per asset, in basis points of what
+    /// they paid in: the entry fee shares come out of it, so the weights sum to less than 100%
+    /// whenever a fee is set. Follows the current allocation, or the targets for an empty fund.
+    /// All zero for a value too small to mint a share unit.
+    pub fn get_effective_weights_after_fees(&self, deposit_value: U128) -> Vec<AssetWeight> {
+        let nav = self.internal_nav();
+        let minted = self.internal_shares_for_value(deposit_value.0);
+        let net = minted - minted * u128::from(self.entry_fee_bps.0) / 10000;
+        let depositor_value = if minted == 0 {
+            0
+        } else {
+            Self::mul_div(net, nav + deposit_value.0, self.total_shares.0 + minted)
+        };
+
+        let allocation: Vec<(AssetId, u64)> = if nav == 0 {
+            self.assets
+                .iter()
+                .map(|(asset_id, holding)| (asset_id, holding.weight.0))
+                .collect()
+        } else {
+            self.get_value_weights()
+                .into_iter()
+                .map(|(asset_id, weight)| (asset_id, weight.0))
+                .collect()
+        };
+        allocation
+            .into_iter()
+            .map(|(asset_address, weight)| AssetWeight {
+                weight: U64((depositor_value * u128::from(weight))
+                    .checked_div(deposit_value.0)
+                    .unwrap_or(0) as u64),
+                asset_address,
+            })
+            .collect()
+    }
+
+    pub fn get_holdings(&self) -> Vec<(AssetId, AssetHolding)> {
+        self.assets.iter().collect()
+    }
+
+    pub fn get_assets(&self) -> Vec<AssetId> {
+        self.assets.keys().collect()
+    }
+
+    // Weighted assets the oracle hasn't priced yet; all must be priced before rebalancing
+    pub fn get_unpriced_assets(&self) -> Vec<AssetId> {
+        self.assets
+            .iter()
+            .filter(|(_, h)| h.weight.0 > 0 && (h.last_price.0 == 0 || h.last_updated.0 == 0))
+            .map(|(asset_id, _)| asset_id)
+            .collect()
+    }
+
+    // One-call oracle monitoring: every asset's price, its age and whether it's stale
+    pub fn get_price_health(&self) -> Vec<PriceHealth> {
+        let now = env::block_timestamp();
+        self.assets
+            .iter()
+            .map(|(asset_id, holding)| {
+                let age_ns = now.saturating_sub(holding.last_updated.0);
+                let too_old = self.max_price_age_ns.0 > 0 && age_ns > self.max_price_age_ns.0;
+                PriceHealth {
+                    asset_id,
+                    price: holding.last_price,
+                    age_ns: U64(age_ns),
+                    is_stale: holding.last_price.0 == 0 || too_old,
+                }
+            })
+            .collect()
+    }
+
+    pub fn is_asset(&self, asset_id: AssetId) -> bool {
+        self.assets.get(&asset_id).is_some()
+    }
+
+    // Weights from highest to lowest; equal weights are ordered by keccak256(asset id ||
+    // little-endian seed), so changing the seed rotates ties without favouring any account id
+    pub fn get_weights_sorted_seeded(&self) -> Vec<AssetWeight> {
+        let seed = self.ordering_seed.0.to_le_bytes();
+        let mut weights: Vec<(Vec<u8>, AssetWeight)> = self
+            .get_weights()
+            .into_iter()
+            .map(|w| {
+                let key = env::keccak256(&[w.asset_address.as_bytes(), &seed].concat());
+                (key, w)
+            })
+            .collect();
+        weights.sort_by(|(a_key, a), (b_key, b)| {
+            b.weight.cmp(&a.weight).then_with(|| a_key.cmp(b_key))
+        });
+        weights.into_iter().map(|(_, w)| w).collect()
+    }
+
+    pub fn get_weights_above(&self, min_bps: U64) -> Vec<AssetWeight> {
+        self.get_weights()
+            .into_iter()
+            .filter(|w| w.weight >= min_bps)
+            .collect()
+    }
+
+    // Highest- and lowest-weighted assets, ignoring zero weights
+    pub fn get_weight_extremes(&self) -> (Option<AssetWeight>, Option<AssetWeight>) {
+        let weights: Vec<AssetWeight> = self
+            .get_weights()
+            .into_iter()
+            .filter(|w| w.weight.0 > 0)
+            .collect();
+        let highest = weights.iter().max_by_key(|w| w.weight.0).cloned();
+        let lowest = weights.iter().min_by_key(|w| w.weight.0).cloned();
+        (highest, lowest)
+    }
+
+    // Weight changes `update_weights(proposed)` would make, sorted by asset. New assets show up
+    // with an old weight of 0 and removed ones with a new weight of 0; nothing is applied.
+    pub fn diff_weights(&self, proposed: Vec<AssetWeight>) -> Vec<WeightDiff> {
+        let mut new_weights: std::collections::BTreeMap<AssetId, u64> =
+            std::collections::BTreeMap::new();
+        for update in proposed {
+            new_weights.insert(update.asset_address, update.weight.0);
+        }
+
+        new_weights
+            .into_iter()
+            .filter_map(|(asset_address, new)| {
+                let old = self.assets.get(&asset_address).map_or(0, |h| h.weight.0);
+                (old != new).then(|| WeightDiff {
+                    asset_address,
+                    old: U64(old),
+                    new: U64(new),
+                    delta: new as i64 - old as i64,
+                })
+            })
+            .collect()
+    }
+
+    // Hex sha256 over the (asset, weight) pairs sorted by asset, so clients can cheaply detect changes
+    pub fn get_composition_hash(&self) -> String {
+        let mut pairs: Vec<(AssetId, u64)> =
+            self.assets.iter().map(|(k, v)| (k, v.weight.0)).collect();
+        pairs.sort();
+        let bytes = near_sdk::borsh::to_vec(&pairs).expect("Failed to serialize weights");
+        env::sha256(&bytes)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    // Quote-asset value of a single holding; zero for unpriced or unknown assets
+    pub fn get_asset_value(&self, asset_id: AssetId) -> U128 {
+        U128(
+            self.assets
+                .get(&asset_id)
+                .map_or(0, |h| Self::asset_value(&h)),
+        )
+    }
+
+    // Net asset value in quote-asset units, using the last oracle prices
+    pub fn get_nav(&self) -> U128 {
+        U128(self.internal_nav())
+    }
+
+    // The `remove_asset_into` swap in flight, if any
+    pub fn get_liquidation(&self) -> Option<Liquidation> {
+        self.liquidation.clone()
+    }
+
+    // Signed quote-asset value each asset is over (+) or under (-) its target weight
+    pub fn get_drift_value(&self) -> Vec<(AssetId, i128)> {
+        let nav = self.internal_nav();
+        self.assets
+            .iter()
+            .map(|(asset_id, holding)| {
+                let current = Self::asset_value(&holding) as i128;
+                let target = Self::target_value(nav, &holding) as i128;
+                (asset_id, current - target)
+            })
+            .collect()
+    }
+
+    // Each asset's allocation minus its effective target, in basis points of NAV, largest
+    // deviation first (overweight positive). All zero for a fund without value.
+    pub fn get_assets_by_drift(&self) -> Vec<(AssetId, i64)> {
+        let nav = self.internal_nav();
+        let mut drifts: Vec<(AssetId, i64)> = self
+            .effective_holdings()
+            .into_iter()
+            .map(|(asset_id, holding)| {
+                let allocation = (Self::asset_value(&holding) * u128::from(WEIGHT_DENOMINATOR))
+                    .checked_div(nav)
+                    .map_or(0, |allocation| allocation as i64 - holding.weight.0 as i64);
+                (asset_id, allocation)
+            })
+            .collect();
+        drifts.sort_by_key(|(_, drift)| std::cmp::Reverse(drift.unsigned_abs()));
+        drifts
+    }
+
+    // Whether any asset has drifted further than `threshold_bps` from its effective target,
+    // regardless of the rebalance interval. For alerting at an urgency other than
+    // drift_tolerance_bps.
+    pub fn is_rebalance_overdue(&self, threshold_bps: U64) -> bool {
+        self.internal_max_drift_bps() > threshold_bps.0
+    }
+
+    // Registered share holders (including those currently holding zero shares), in a stable order
+    pub fn get_shareholders(&self, from_index: u64, limit: u64) -> Vec<(AccountId, U128)> {
+        self.shares
+            .iter()
+            .skip(from_index as usize)
+            .take(limit as usize)
+            .collect()
+    }
+
+    pub fn get_shareholder_count(&self) -> u64 {
+        self.shares.len()
+    }
+
+    // Invariant check for monitors: individual share balances must add up to `total_shares`
+    pub fn verify_share_accounting(&self) -> bool {
+        let sum: u128 = self.shares.values().map(|balance| balance.0).sum();
+        sum == self.total_shares.0
+    }
+
+    // What a rebalance at current prices would trade, without executing it
+    pub fn estimate_rebalance(&self) -> RebalanceEstimate {
+        let plan = self.internal_rebalance_plan();
+        RebalanceEstimate {
+            num_trades: plan.len() as u64,
+            total_notional: U128(plan.iter().map(|(_, delta)| delta.unsigned_abs()).sum()),
+            nav: U128(self.internal_nav()),
+        }
+    }
+
+    // Realized allocation: each asset's share of NAV in basis points, summing to exactly 10000
+    // (rounding dust goes to the largest holding). All zero for a fund without value.
+    pub fn get_value_weights(&self) -> Vec<(AssetId, U64)> {
+        let values: Vec<(AssetId, u128)> = self
+            .assets
+            .iter()
+            .map(|(asset_id, holding)| (asset_id, Self::asset_value(&holding)))
+            .collect();
+        if values.iter().all(|(_, v)| *v == 0) {
+            return values
+                .into_iter()
+                .map(|(asset_id, _)| (asset_id, U64(0)))
+                .collect();
+        }
+
+        Self::normalize_weights(&values)
+            .into_iter()
+            .map(|(asset_id, weight)| (asset_id, U64(weight)))
+            .collect()
+    }
+
+    // Everything a listing site shows for the index, in one call
+    pub fn get_index_listing(&self) -> IndexListing {
+        let value_weights = self.get_value_weights();
+        let constituents = self
+            .assets
+            .iter()
+            .map(|(asset_id, holding)| {
+                let value_bps = value_weights
+                    .iter()
+                    .find(|(id, _)| *id == asset_id)
+                    .map_or(U64(0), |(_, bps)| *bps);
+                IndexConstituent {
+                    asset_id,
+                    label: holding.label,
+                    weight: holding.weight,
+                    value_bps,
+                }
+            })
+            .collect();
+        IndexListing {
+            metadata: self.fund_metadata(),
+            constituents,
+        }
+    }
+
+    /// Tracking error of the realized allocation against the target weights, in basis points:
+    /// sqrt(sum((value_bps - weight_bps)^2)) over all assets, rounded down. Zero for an empty fund.
+    pub fn get_tracking_error_bps(&self) -> U64 {
+        if self.internal_nav() == 0 {
+            return U64(0);
+        }
+        let sum_of_squares: u64 = self
+            .get_value_weights()
+            .into_iter()
+            .map(|(asset_id, value_bps)| {
+                let deviation = value_bps.0.abs_diff(self.expect_asset(&asset_id).weight.0);
+                deviation * deviation
+            })
+            .sum();
+        U64(Self::isqrt(sum_of_squares))
+    }
+
+    // Assets whose current value allocation has drifted outside their weight bounds
+    pub fn get_bound_breaches(&self) -> Vec<(AssetId, BreachKind)> {
+        let nav = self.internal_nav();
+        if nav == 0 {
+            return vec![];
+        }
+
+        self.assets
+            .iter()
+            .filter_map(|(asset_id, holding)| {
+                let allocation = Self::asset_value(&holding) * u128::from(WEIGHT_DENOMINATOR) / nav;
+                if allocation > u128::from(holding.max_weight.0) {
+                    Some((asset_id, BreachKind::AboveMax))
+                } else if allocation < u128::from(holding.min_weight.0) {
+                    Some((asset_id, BreachKind::BelowMin))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    // Everything a keeper checks before calling `rebalance`, with the raw inputs
+    pub fn get_rebalance_readiness(&self) -> RebalanceReadiness {
+        let next = self.last_rebalance.0 + self.rebalance_interval.0;
+        let now = self.interval_now();
+        let max_drift_bps = self.internal_max_drift_bps();
+        let interval_elapsed = now >= next;
+        RebalanceReadiness {
+            interval_elapsed,
+            blocks_remaining: U64(next.saturating_sub(now)),
+            max_drift_bps: U64(max_drift_bps),
+            tolerance_bps: self.drift_tolerance_bps,
+            ready: interval_elapsed && max_drift_bps > self.drift_tolerance_bps.0,
+        }
+    }
+
+    // Dashboard summary in a single call
+    pub fn get_portfolio_stats(&self) -> PortfolioStats {
+        PortfolioStats {
+            asset_count: self.assets.len(),
+            active_asset_count: self.assets.values().filter(|h| h.weight.0 > 0).count() as u64,
+            nav: U128(self.internal_nav()),
+            total_shares: self.total_shares,
+            nav_per_share: U128(self.internal_nav_per_share()),
+            total_weight: U64(self.assets.values().map(|h| h.weight.0).sum()),
+        }
+    }
+
+    // NAV if the given assets were priced differently; the rest keep their stored price
+    pub fn nav_with_prices(&self, prices: Vec<(AssetId, U128)>) -> U128 {
+        for (asset_id, _) in prices.iter() {
+            self.expect_asset(asset_id);
+        }
+        let nav = self
+            .assets
+            .iter()
+            .map(|(asset_id, mut holding)| {
+                if let Some((_, price)) = prices.iter().find(|(id, _)| *id == asset_id) {
+                    holding.last_price = *price;
+                }
+                Self::asset_value(&holding)
+            })
+            .sum();
+        U128(nav)
+    }
+
+    // Stress test: the rebalance plan if the given assets were priced differently. Assets left
+    // out keep their stored price; nothing is written.
+    pub fn simulate_rebalance_with_prices(
+        &self,
+        prices: Vec<(AssetId, U128)>,
+    ) -> Vec<(AssetId, i128)> {
+        let mut holdings: Vec<(AssetId, AssetHolding)> = self.assets.iter().collect();
+        for (asset_id, price) in prices {
+            let (_, holding) = holdings
+                .iter_mut()
+                .find(|(id, _)| *id == asset_id)
+                .unwrap_or_else(|| env::panic_str(&format!("Unknown asset {}", asset_id)));
+            holding.last_price = price;
+        }
+        self.rebalance_plan_for(holdings)
+    }
+
+    // The rebalance plan that would follow adopting `proposed` (applied on top of the current
+    // weights, like `update_weights`) at current prices; nothing is written
+    pub fn trades_for_weights(&self, proposed: Vec<AssetWeight>) -> Vec<(AssetId, i128)> {
+        let mut holdings: Vec<(AssetId, AssetHolding)> = self.assets.iter().collect();
+        for update in proposed {
+            match holdings
+                .iter_mut()
+                .find(|(id, _)| *id == update.asset_address)
+            {
+                Some((_, holding)) => holding.weight = update.weight,
+                None => holdings.push((update.asset_address, Self::new_holding(update.weight))),
+            }
+        }
+        self.rebalance_plan_for(holdings)
+    }
+
+    pub fn get_total_fees_collected(&self) -> U128 {
+        self.total_fees_collected
+    }
+
+    pub fn get_fees(&self) -> FeeConfig {
+        FeeConfig {
+            entry_fee_bps: self.entry_fee_bps,
+            exit_fee_bps: self.exit_fee_bps,
+            management_fee_bps: self.management_fee_bps,
+            keeper_reward_bps: self.keeper_reward_bps,
+            fee_recipient: self.fee_recipient.clone(),
+        }
+    }
+
+    pub fn get_rebalance_count(&self) -> U64 {
+        self.rebalance_count
+    }
+
+    // Earliest block height (or ms timestamp) at which the next rebalance is due
+    pub fn get_next_rebalance(&self) -> U64 {
+        U64(self.last_rebalance.0 + self.rebalance_interval.0)
+    }
+
+    // Smallest deposit value (quote units) that mints at least one share unit at the current NAV
+    pub fn min_deposit_for_one_share(&self) -> U128 {
+        if self.total_shares.0 == 0 {
+            return U128(self.quote_units_per_share());
+        }
+        U128(self.internal_nav().div_ceil(self.total_shares.0).max(1))
+    }
+
+    // NAV per share scaled by NAV_PER_SHARE_SCALE; 1.0 while there are no shares
+    pub fn get_nav_per_share(&self) -> U128 {
+        U128(self.internal_nav_per_share())
+    }
+
+    // NAV snapshots, oldest first
+    pub fn get_nav_history(&self, from_index: u64, limit: u64) -> Vec<NavSnapshot> {
+        let len = self.nav_history.len();
+        (from_index..len)
+            .take(limit as usize)
+            .filter_map(|index| self.nav_history.get((self.nav_history_start + index) % len))
+            .collect()
+    }
+
+    pub fn get_share_price_history(&self, from_index: u64, limit: u64) -> Vec<SharePricePoint> {
+        self.get_nav_history(from_index, limit)
+            .into_iter()
+            .zip(from_index..)
+            .map(|(snapshot, index)| SharePricePoint {
+                index,
+                timestamp: snapshot.timestamp,
+                nav_per_share: snapshot.nav_per_share,
+            })
+            .collect()
+    }
+
+    pub fn get_inception(&self) -> U64 {
+        self.inception_ts
+    }
+
+    // Nanoseconds since inception, for annualizing performance
+    pub fn get_age_ns(&self) -> U64 {
+        U64(env::block_timestamp().saturating_sub(self.inception_ts.0))
+    }
+
+    // Weight, price and pause operations, oldest first
+    pub fn get_action_history(&self, from_index: u64, limit: u64) -> Vec<ActionRecord> {
+        let len = self.curator_actions.len();
+        (from_index..len)
+            .take(limit as usize)
+            .filter_map(|index| {
+                self.curator_actions
+                    .get((self.curator_actions_start + index) % len)
+            })
+            .collect()
+    }
+
+    // The hash `commit_weights` expects for `updates` and `salt`
+    pub fn hash_weights(&self, updates: Vec<AssetWeight>, salt: String) -> Base58CryptoHash {
+        Self::weights_hash(&updates, &salt)
+    }
+
+    // Curator changes, oldest first
+    pub fn get_curator_history(&self) -> Vec<CuratorChange> {
+        let len = self.curator_history.len();
+        (0..len)
+            .filter_map(|index| {
+                self.curator_history
+                    .get((self.curator_history_start + index) % len)
+            })
+            .collect()
+    }
+
+    pub fn get_curator(&self) -> Option<AccountId> {
+        self.curator_address.clone()
+    }
+
+    pub fn get_oracle(&self) -> Option<AccountId> {
+        self.oracle_address.clone()
+    }
+
+    /// Estimates the storage held by `assets`. Each entry is three records: the key's index, the
+    /// key in the key vector and the value in the value vector, each under a short prefix.
+    pub fn get_assets_storage_info(&self) -> AssetsStorageInfo {
+        let prefix_len = near_sdk::borsh::to_vec(&StorageKey::Assets).unwrap().len() as u64;
+        let approx_bytes = self
+            .assets
+            .iter()
+            .map(|(asset_id, holding)| {
+                let key_len = near_sdk::borsh::to_vec(&asset_id).unwrap().len() as u64;
+                let value_len = near_sdk::borsh::to_vec(&holding).unwrap().len() as u64;
+                // the index record's key and each vector record's key carry the map prefix plus
+                // a one-byte tag; the index record's value and the vector keys are u64 indices
+                3 * (prefix_len + 1 + 8) + 2 * key_len + value_len + 3 * STORAGE_RECORD_OVERHEAD
+            })
+            .sum();
+        AssetsStorageInfo {
+            count: self.assets.len(),
+            approx_bytes,
+        }
+    }
+
+    pub fn get_storage_usage(&self) -> U64 {
+        U64(env::storage_usage())
+    }
+
+    // NEAR locked for the bytes currently used by the contract state
+    pub fn get_storage_cost(&self) -> U128 {
+        U128(env::storage_usage() as u128 * env::storage_byte_cost().as_yoctonear())
+    }
+}
+
+impl IndexFund {
+    fn assert_curator(&self) {
+        let curator = self
+            .curator_address
+            .as_ref()
+            .expect("curator not registered");
+        require!(env::predecessor_account_id() == *curator, "Unauthorized");
+    }
+
+    // The curator can set any weights, the delegate only within its delta of the current ones
+    fn assert_can_update_weights(&self, caller: &AccountId, updates: &[AssetWeight]) {
+        if self.delegate.as_ref() == Some(caller) {
+            for update in updates.iter() {
+                let old = self
+                    .assets
+                    .get(&update.asset_address)
+                    .map_or(0, |h| h.weight.0);
+                require!(
+                    old.abs_diff(update.weight.0) <= self.delegate_max_delta_bps.0,
+                    format!("Weight change too large for {}", update.asset_address)
+                );
+            }
+        } else {
+            let curator = self
+                .curator_address
+                .as_ref()
+                .expect("curator not registered");
+            require!(caller == curator, "Unauthorized");
+        }
+    }
+
+    // The weights `updates` would set: as given, or rescaled to exactly 100% if their sum is
+    // within the tolerance. Panics if the sum, an asset's bounds or strict zeroing reject them.
+    fn checked_weight_updates(&self, updates: &[AssetWeight]) -> Vec<AssetWeight> {
+        // Create a temporary copy of current weights
+        let mut new_weights: std::collections::HashMap<AccountId, U64> =
+            self.assets.iter().map(|(k, v)| (k, v.weight)).collect();
+
+        // Apply updates
+        for update in updates.iter() {
+            new_weights.insert(update.asset_address.clone(), update.weight);
+        }
+
+        // Verify total weight is WEIGHT_DENOMINATOR (100%), give or take the tolerance
+        let total_weight: u64 = new_weights.values().map(|&w| u64::from(w)).sum();
+        require!(
+            total_weight.abs_diff(WEIGHT_DENOMINATOR) <= self.weight_sum_tolerance_bps.0,
+            "Final weights must sum to 100%"
+        );
+
+        // A sum within tolerance is scaled so every weight is updated to sum to exactly 100%
+        let updates = if total_weight == WEIGHT_DENOMINATOR {
+            updates.to_vec()
+        } else {
+            let mut weights: Vec<(AssetId, u128)> = new_weights
+                .iter()
+                .map(|(asset_id, w)| (asset_id.clone(), u128::from(w.0)))
+                .collect();
+            weights.sort();
+            let renormalized: Vec<AssetWeight> = Self::normalize_weights(&weights)
+                .into_iter()
+                .map(|(asset_address, weight)| AssetWeight {
+                    weight: U64(weight),
+                    asset_address,
+                })
+                .collect();
+            log!("Renormalized weights summing to {} bps", total_weight);
+            new_weights = renormalized
+                .iter()
+                .map(|w| (w.asset_address.clone(), w.weight))
+                .collect();
+            renormalized
+        };
+
+        for update in updates.iter() {
+            if let Some(holding) = self.assets.get(&update.asset_address) {
+                require!(
+                    holding.min_weight <= update.weight && update.weight <= holding.max_weight,
+                    format!("Weight out of bounds for {}", update.asset_address)
+                );
+                // a zero-weight asset is never bought, so its balance would sit out of the index
+                if update.weight.0 == 0 && holding.balance.0 > 0 {
+                    let message = format!(
+                        "Zeroing the weight of {} which still holds a balance",
+                        update.asset_address
+                    );
+                    if self.strict_weight_zeroing {
+                        env::panic_str(&message);
+                    }
+                    log!("{}", message);
+                }
+            }
+        }
+
+        // The sum is right, but tiny weights usually mean the curator's normalization leaked dust
+        let mut dust_assets: Vec<&str> = new_weights
+            .iter()
+            .filter(|(_, w)| w.0 > 0 && w.0 <= DUST_WEIGHT_BPS)
+            .map(|(asset_id, _)| asset_id.as_str())
+            .collect();
+        if !dust_assets.is_empty() {
+            dust_assets.sort();
+            log!("Suspicious dust weights for {}", dust_assets.join(", "));
+        }
+
+        updates
+    }
+
+    // Validates that the weights will sum to 100% and applies them, adding any new assets
+    fn internal_update_weights(&mut self, updates: &[AssetWeight]) {
+        let updates = &self.checked_weight_updates(updates);
+
+        // Apply updates only after verification; a direct update cancels any glide
+        self.weight_glide = None;
+        for update in updates {
+            if let Some(mut holding) = self.assets.get(&update.asset_address) {
+                holding.weight = update.weight;
+                self.assets.insert(&update.asset_address, &holding);
+            } else {
+                self.assets
+                    .insert(&update.asset_address, &Self::new_holding(update.weight));
+                IndexFundEvent::AssetAdded {
+                    asset_address: update.asset_address.clone(),
+                    initial_weight: update.weight,
+                }
+                .emit(&self.event_standard, &self.event_version);
+            }
+        }
+
+        env::log_str(&format!("Updated weights: {:?}", updates));
+        self.record_action(ActionKind::UpdateWeights);
+
+        if self.auto_prune_zero_weight {
+            // a liquidating asset shows no balance, but its swap may still refund some
+            let liquidating = self.liquidation.as_ref().map(|l| l.asset_id.clone());
+            for update in updates.iter().filter(|u| u.weight.0 == 0) {
+                if liquidating.as_ref() == Some(&update.asset_address) {
+                    continue;
+                }
+                if self.expect_asset(&update.asset_address).balance.0 == 0 {
+                    self.assets.remove(&update.asset_address);
+                    IndexFundEvent::AssetRemoved {
+                        asset_address: update.asset_address.clone(),
+                    }
+                    .emit(&self.event_standard, &self.event_version);
+                }
+            }
+        }
+    }
+
+    fn new_holding(weight: U64) -> AssetHolding {
+        AssetHolding {
+            balance: U128(0),
+            weight,
+            last_price: U128(0),
+            last_updated: U64(env::block_timestamp()),
+            deposits_enabled: true,
+            tradeable_after_ns: U64(0),
+            decimals: 0,
+            max_balance: None,
+            label: None,
+            min_weight: U64(0),
+            max_weight: U64(WEIGHT_DENOMINATOR),
+            market_cap: U128(0),
+            fee_on_transfer: false,
+            oracle: None,
+        }
+    }
+
+    fn set_curator(&mut self, new: AccountId) {
+        let change = CuratorChange {
+            old: self.curator_address.replace(new.clone()),
+            new,
+            block_height: U64(env::block_height()),
+        };
+        if self.curator_history.len() < MAX_CURATOR_HISTORY {
+            self.curator_history.push(&change);
+        } else {
+            self.curator_history
+                .replace(self.curator_history_start, &change);
+            self.curator_history_start = (self.curator_history_start + 1) % MAX_CURATOR_HISTORY;
+        }
+    }
+
+    fn record_action(&mut self, action: ActionKind) {
+        let record = ActionRecord {
+            action,
+            block_height: U64(env::block_height()),
+            caller: env::predecessor_account_id(),
+        };
+        if self.curator_actions.len() < MAX_ACTION_HISTORY {
+            self.curator_actions.push(&record);
+        } else {
+            self.curator_actions
+                .replace(self.curator_actions_start, &record);
+            self.curator_actions_start = (self.curator_actions_start + 1) % MAX_ACTION_HISTORY;
+        }
+    }
+
+    // Current time in the unit the rebalance interval is measured in
+    fn interval_now(&self) -> u64 {
+        match self.interval_unit {
+            IntervalUnit::Blocks => env::block_height(),
+            IntervalUnit::Milliseconds => env::block_timestamp_ms(),
+        }
+    }
+
+    fn assert_oracle(&self) {
+        let oracle = self.oracle_address.as_ref().expect("oracle not registered");
+        require!(env::predecessor_account_id() == *oracle, "Unauthorized");
+    }
+
+    fn assert_price_source(&self, asset_id: &AssetId) {
+        match self.expect_asset(asset_id).oracle {
+            Some(oracle) => require!(env::predecessor_account_id() == oracle, "Unauthorized"),
+            None => self.assert_oracle(),
+        }
+    }
+
+    fn assert_prices_live(&self) {
+        require!(!self.prices_frozen, "Price updates are frozen");
+    }
+
+    fn internal_set_price(&mut self, asset_id: &AssetId, price: Price, timestamp: U64) {
+        let mut holding = self.expect_asset(asset_id);
+
+        // Reject fat-finger pushes that move a known price too far in one update
+        let max_move = u128::from(self.max_price_move_bps.0);
+        let old = holding.last_price.0;
+        if max_move > 0 && old > 0 {
+            let change = old.abs_diff(price.0);
+            require!(
+                change * 10000 <= old * max_move,
+                format!("Price move too large for {}", asset_id)
+            );
+        }
+
+        holding.last_price = price;
+        holding.last_updated = timestamp;
+        self.assets.insert(asset_id, &holding);
+    }
+
+    fn weights_hash(updates: &[AssetWeight], salt: &str) -> Base58CryptoHash {
+        let preimage = near_sdk::borsh::to_vec(&(updates, salt)).unwrap();
+        Base58CryptoHash::from(env::sha256_array(&preimage))
+    }
+
+    fn fund_metadata(&self) -> FundMetadata {
+        self.metadata.clone().unwrap_or(FundMetadata {
+            name: "Index Fund Share".to_string(),
+            symbol: "IFS".to_string(),
+            icon: None,
+            reference: None,
+        })
+    }
+
+    fn is_reporter(&self) -> bool {
+        self.reporter.as_ref() == Some(&env::predecessor_account_id())
+    }
+
+    fn assert_owner(&self) {
+        let owner = self.owner_id.as_ref().expect("owner not set");
+        require!(env::predecessor_account_id() == *owner, "Unauthorized");
+    }
+
+    fn assert_no_liquidation(&self) {
+        require!(self.liquidation.is_none(), "A liquidation is in flight");
+    }
+
+    fn assert_not_winding_down(&self) {
+        require!(!self.winding_down, "Fund is winding down");
+    }
+
+    fn assert_not_paused(&self) {
+        require!(!self.paused, "Contract is paused");
+    }
+
+    // Not paused, and past the grace period following the last unpause
+    fn assert_active(&self) {
+        self.assert_not_paused();
+        require!(
+            !self.in_unpause_grace(),
+            "Contract is in its post-unpause grace period"
+        );
+    }
+
+    fn in_unpause_grace(&self) -> bool {
+        self.unpaused_at.0 > 0
+            && env::block_timestamp() < self.unpaused_at.0 + self.unpause_grace_ns.0
+    }
+
+    fn expect_asset(&self, asset_id: &AssetId) -> AssetHolding {
+        self.assets
+            .get(asset_id)
+            .unwrap_or_else(|| env::panic_str(&format!("Unknown asset {}", asset_id)))
+    }
+
+    // Quote-asset value of `amount` raw token units of the holding's asset
+    fn value_of(holding: &AssetHolding, amount: u128) -> u128 {
+        Self::mul_div(
+            amount,
+            holding.last_price.0,
+            10u128.pow(u32::from(holding.decimals)),
+        )
+    }
+
+    fn asset_value(holding: &AssetHolding) -> u128 {
+        Self::value_of(holding, holding.balance.0)
+    }
+
+    // `a * b / c` rounded down, through a 256-bit product: amounts of 24-decimal tokens times a
+    // price (or a share supply) easily pass u128. Panics only if the result doesn't fit.
+    fn mul_div(a: u128, b: u128, c: u128) -> u128 {
+        require!(c > 0, "Division by zero");
+        if let Some(product) = a.checked_mul(b) {
+            return product / c;
+        }
+
+        const LOW: u128 = u64::MAX as u128;
+        let (a_high, a_low) = (a >> 64, a & LOW);
+        let (b_high, b_low) = (b >> 64, b & LOW);
+        let low_low = a_low * b_low;
+        let high_low = a_high * b_low;
+        let low_high = a_low * b_high;
+        let middle = (low_low >> 64) + (high_low & LOW) + (low_high & LOW);
+        let low = (middle << 64) | (low_low & LOW);
+        let high = a_high * b_high + (high_low >> 64) + (low_high >> 64) + (middle >> 64);
+        require!(high < c, "Arithmetic overflow");
+
+        // long division of (high, low) by c, a bit at a time; the remainder stays below c
+        let mut remainder = high;
+        let mut quotient = 0u128;
+        for bit in (0..128).rev() {
+            let carry = remainder >> 127;
+            remainder = (remainder << 1) | ((low >> bit) & 1);
+            quotient <<= 1;
+            if carry == 1 || remainder >= c {
+                remainder = remainder.wrapping_sub(c);
+                quotient |= 1;
+            }
+        }
+        quotient
+    }
+
+    // Integer square root, rounded down (Newton's method)
+    fn isqrt(n: u64) -> u64 {
+        if n < 2 {
+            return n;
+        }
+        let mut x = n;
+        let mut y = x.div_ceil(2);
+        while y < x {
+            x = y;
+            y = (x + n / x) / 2;
+        }
+        x
+    }
+
+    // Splits WEIGHT_DENOMINATOR proportionally to `values`; rounding dust goes to the largest value
+    fn normalize_weights(values: &[(AssetId, u128)]) -> Vec<(AssetId, u64)> {
+        let total: u128 = values.iter().map(|(_, v)| v).sum();
+        let mut weights: Vec<(AssetId, u64)> = values
+            .iter()
+            .map(|(asset_id, value)| {
+                (
+                    asset_id.clone(),
+                    (value * u128::from(WEIGHT_DENOMINATOR) / total) as u64,
+                )
+            })
+            .collect();
+
+        let dust = WEIGHT_DENOMINATOR - weights.iter().map(|(_, w)| w).sum::<u64>();
+        if let Some(index) = Self::dust_recipient(values) {
+            weights[index].1 += dust;
+        }
+        weights
+    }
+
+    // Index of the asset that absorbs rounding dust: the largest value, ties going to the
+    // lexicographically smallest asset id so the result never depends on map order
+    fn dust_recipient(values: &[(AssetId, u128)]) -> Option<usize> {
+        values
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.1.cmp(&b.1).then_with(|| b.0.cmp(&a.0)))
+            .map(|(index, _)| index)
+    }
+
+    fn target_value(nav: u128, holding: &AssetHolding) -> u128 {
+        nav * u128::from(holding.weight.0) / u128::from(WEIGHT_DENOMINATOR)
+    }
+
+    // Quote-asset value to buy (+) or sell (-) per asset to get back to target weights.
+    // Assets already on target are left out. Locked assets can't trade, so the rest are
+    // rebalanced among themselves by their relative weights.
+    // Queued redemptions left `balance` when they were requested, so assets already promised
+    // out are neither counted in the targets nor traded.
+    fn internal_rebalance_plan(&self) -> Vec<(AssetId, i128)> {
+        self.rebalance_plan_for(self.effective_holdings())
+    }
+
+    // Holdings with their weight replaced by the interpolated weight of any ongoing glide.
+    // Interpolated weights may not sum to exactly 100%; the rebalance plan normalizes them.
+    fn effective_holdings(&self) -> Vec<(AssetId, AssetHolding)> {
+        let holdings = self.assets.iter().collect();
+        let Some(glide) = self.weight_glide.as_ref() else {
+            return holdings;
+        };
+
+        let elapsed = env::block_height()
+            .saturating_sub(glide.start_block.0)
+            .min(glide.glide_blocks.0);
+        holdings
+            .into_iter()
+            .map(|(asset_id, mut holding)| {
+                let from = glide
+                    .from
+                    .iter()
+                    .find(|w| w.asset_address == asset_id)
+                    .map_or(0, |w| w.weight.0);
+                let to = holding.weight.0;
+                let moved = (i128::from(to) - i128::from(from)) * i128::from(elapsed)
+                    / i128::from(glide.glide_blocks.0);
+                holding.weight = U64((i128::from(from) + moved) as u64);
+                (asset_id, holding)
+            })
+            .collect()
+    }
+
+    // The rebalance plan for an arbitrary set of holdings, e.g. with hypothetical prices
+    fn rebalance_plan_for(&self, holdings: Vec<(AssetId, AssetHolding)>) -> Vec<(AssetId, i128)> {
+        let now = env::block_timestamp();
+        let tradeable: Vec<(AssetId, AssetHolding)> = holdings
+            .into_iter()
+            .filter(|(_, holding)| holding.tradeable_after_ns.0 <= now)
+            .collect();
+        let nav: u128 = tradeable.iter().map(|(_, h)| Self::asset_value(h)).sum();
+        let total_weight: u128 = tradeable.iter().map(|(_, h)| u128::from(h.weight.0)).sum();
+        if total_weight == 0 {
+            return vec![];
+        }
+
+        let mut plan: Vec<(AssetId, i128)> = tradeable
+            .into_iter()
+            .map(|(asset_id, holding)| {
+                let target = (nav * u128::from(holding.weight.0) / total_weight) as i128;
+                let value = Self::asset_value(&holding) as i128;
+                let delta = match self.rebalance_mode {
+                    RebalanceMode::Full => target - value,
+                    RebalanceMode::Band { width_bps } => {
+                        let half_width = (nav * u128::from(width_bps.0)
+                            / u128::from(WEIGHT_DENOMINATOR))
+                            as i128;
+                        let (lower, upper) = (target - half_width, target + half_width);
+                        value.clamp(lower.max(0), upper) - value
+                    }
+                };
+                // buy enough that what arrives after the transfer fee is the shortfall
+                if delta > 0 && holding.fee_on_transfer {
+                    let received_bps = 10000 - FEE_ON_TRANSFER_ALLOWANCE_BPS;
+                    return (asset_id, (delta * 10000 + received_bps - 1) / received_bps);
+                }
+                (asset_id, delta)
+            })
+            .filter(|(_, delta)| *delta != 0)
+            .collect();
+        if self.rebalance_strategy == RebalanceStrategy::SellFirst {
+            // stable, so sells and buys each keep their relative order
+            plan.sort_by_key(|(_, delta)| *delta > 0);
+        }
+        plan
+    }
+
+    // An empty fund is priced at what the next deposit pays per share unit (1.0 at
+    // SHARE_BASE_DECIMALS)
+    fn internal_nav_per_share(&self) -> u128 {
+        if self.total_shares.0 > 0 {
+            return Self::mul_div(
+                self.internal_nav(),
+                NAV_PER_SHARE_SCALE,
+                self.total_shares.0,
+            );
+        }
+        NAV_PER_SHARE_SCALE.saturating_mul(self.quote_units_per_share())
+    }
+
+    // Quote units the first deposit pays per share unit
+    fn quote_units_per_share(&self) -> u128 {
+        10u128.pow(u32::from(SHARE_BASE_DECIMALS - self.share_decimals))
+    }
+
+    // Largest distance between an asset's share of NAV and its effective target weight, in bps
+    fn internal_max_drift_bps(&self) -> u64 {
+        let nav = self.internal_nav();
+        if nav == 0 {
+            return 0;
+        }
+        self.effective_holdings()
+            .iter()
+            .map(|(_, holding)| {
+                let allocation =
+                    (Self::asset_value(holding) * u128::from(WEIGHT_DENOMINATOR) / nav) as u64;
+                allocation.abs_diff(holding.weight.0)
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn internal_nav(&self) -> u128 {
+        self.assets.values().map(|h| Self::asset_value(&h)).sum()
+    }
+
+    /// Credits a deposit of `amount` of `asset_id` and mints shares to `account_id` pro rata to the
+    /// value added, less the entry fee. The first deposit mints one share per quote unit. Returns
+    /// the shares minted to `account_id`.
+    pub fn internal_deposit(
+        &mut self,
+        account_id: &AccountId,
+        asset_id: &AssetId,
+        amount: u128,
+    ) -> u128 {
+        self.assert_no_liquidation();
+        self.internal_accrue_management_fee();
+        let mut holding = self.expect_asset(asset_id);
+        require!(holding.last_price.0 > 0, "Asset has no price");
+
+        let value = Self::value_of(&holding, amount);
+        self.settle_orphaned_value();
+        let minted = self.shares_for_value(value);
+
+        holding.balance = U128(holding.balance.0 + amount);
+        self.assets.insert(asset_id, &holding);
+        self.mint_deposit_shares(account_id, minted, value)
+    }
+
+    // Mints `management_fee_bps` a year of the outstanding shares, pro rata to the time elapsed,
+    // to the fee recipient. Minting dilutes holders slightly less than the nominal rate. Runs
+    // before every mint or burn, so shares only pay for the time they were outstanding.
+    fn internal_accrue_management_fee(&mut self) -> u128 {
+        let now = env::block_timestamp();
+        let elapsed = now.saturating_sub(self.management_fee_accrued_at.0);
+        self.management_fee_accrued_at = U64(now);
+
+        let fee = Self::mul_div(
+            self.total_shares.0,
+            u128::from(self.management_fee_bps.0) * u128::from(elapsed),
+            10000 * u128::from(NS_PER_YEAR),
+        );
+        if fee > 0 {
+            let fee_recipient = self.fee_recipient.clone().expect("fee recipient not set");
+            self.internal_mint_shares(&fee_recipient, fee);
+            // what the fee shares are worth once they've diluted the supply
+            self.add_fees_collected(Self::mul_div(self.internal_nav(), fee, self.total_shares.0));
+        }
+        fee
+    }
+
+    fn add_fees_collected(&mut self, value: u128) {
+        self.total_fees_collected = U128(self.total_fees_collected.0 + value);
+    }
+
+    // Shares the next deposit mints to the fee recipient under `SweepToFeeRecipient`; anything
+    // below one share unit can't be swept and goes with the deposit
+    fn swept_orphan_shares(&self) -> u128 {
+        if self.total_shares.0 > 0
+            || self.zero_supply_policy != ZeroSupplyPolicy::SweepToFeeRecipient
+        {
+            return 0;
+        }
+        self.internal_nav() / self.quote_units_per_share()
+    }
+
+    // Applies `zero_supply_policy` to whatever value is left in a fund with no shares outstanding
+    fn settle_orphaned_value(&mut self) {
+        if self.total_shares.0 > 0 {
+            return;
+        }
+        let nav = self.internal_nav();
+        if nav == 0 {
+            return;
+        }
+        match self.zero_supply_policy {
+            ZeroSupplyPolicy::CleanMint => {
+                log!("Minting 1:1 with {} of residual value in the fund", nav);
+            }
+            ZeroSupplyPolicy::SweepToFeeRecipient => {
+                let swept = self.swept_orphan_shares();
+                if swept == 0 {
+                    return;
+                }
+                let fee_recipient = self.fee_recipient.clone().expect("fee recipient not set");
+                self.internal_mint_shares(&fee_recipient, swept);
+                self.add_fees_collected(nav);
+                log!("Swept {} of residual value to {}", nav, fee_recipient);
+            }
+        }
+    }
+
+    // Splits freshly minted deposit shares, bought with `value`, between the depositor and the
+    // entry fee. Returns the depositor's part.
+    fn mint_deposit_shares(&mut self, account_id: &AccountId, minted: u128, value: u128) -> u128 {
+        self.assert_within_share_cap(minted);
+        let fee = minted * u128::from(self.entry_fee_bps.0) / 10000;
+        if fee > 0 {
+            let fee_recipient = self.fee_recipient.clone().expect("fee recipient not set");
+            self.internal_mint_shares(&fee_recipient, fee);
+            self.add_fees_collected(Self::mul_div(value, fee, minted));
+        }
+        self.internal_mint_shares(account_id, minted - fee);
+        self.last_deposit_at
+            .insert(account_id, &U64(env::block_timestamp()));
+        minted - fee
+    }
+
+    fn assert_within_share_cap(&self, minted: u128) {
+        if let Some(cap) = self.max_share_supply {
+            require!(
+                self.total_shares.0 + minted <= cap.0,
+                "Deposit would exceed the share supply cap"
+            );
+        }
+    }
+
+    // Mints the keeper reward; an unregistered caller can still rebalance, it just isn't paid
+    fn reward_keeper(&mut self, caller: &AccountId) {
+        self.internal_accrue_management_fee();
+        let reward = Self::mul_div(
+            self.total_shares.0,
+            u128::from(self.keeper_reward_bps.0),
+            10000,
+        );
+        if reward == 0 {
+            return;
+        }
+        if self.shares.get(caller).is_none() {
+            log!("{} is not registered, skipping the keeper reward", caller);
+            return;
+        }
+        self.internal_mint_shares(caller, reward);
+        // a cost to holders like the fees, so it's counted with them
+        self.add_fees_collected(Self::mul_div(
+            self.internal_nav(),
+            reward,
+            self.total_shares.0,
+        ));
+        log!("Minted {} shares to {} for rebalancing", reward, caller);
+    }
+
+    fn is_locked(&self, account_id: &AccountId) -> bool {
+        !self.winding_down
+            && self
+                .last_deposit_at
+                .get(account_id)
+                .is_some_and(|deposited_at| {
+                    env::block_timestamp() < deposited_at.0 + self.share_lockup_ns.0
+                })
+    }
+
+    fn assert_unlocked(&self, account_id: &AccountId) {
+        require!(!self.is_locked(account_id), "Shares are locked");
+    }
+
+    // An empty `msg` deposits right away; PROPORTIONAL_DEPOSIT_MSG parks the tokens instead.
+    // Returns the amount to refund to the sender.
+    fn internal_ft_on_transfer(&mut self, sender_id: AccountId, amount: U128, msg: String) -> U128 {
+        let asset_id = env::predecessor_account_id();
+        // swap proceeds belong to every holder, so they're credited without minting shares. Only
+        // the DEX, paying out the pending liquidation's token, may use the msg.
+        if msg == LIQUIDATION_MSG {
+            let is_proceeds = self
+                .liquidation
+                .as_ref()
+                .is_some_and(|liquidation| liquidation.token_out == asset_id);
+            require!(
+                is_proceeds && self.dex_id.as_ref() == Some(&sender_id),
+                "Unknown deposit msg"
+            );
+            let mut holding = self.expect_asset(&asset_id);
+            holding.balance = U128(holding.balance.0 + amount.0);
+            self.assets.insert(&asset_id, &holding);
+            log!("Received {} {} from liquidation", amount.0, asset_id);
+            return U128(0);
+        }
+        if self.paused || self.in_unpause_grace() {
+            log!("Contract is paused, refunding");
+            return amount;
+        }
+        if self.winding_down {
+            log!("Fund is winding down, refunding");
+            return amount;
+        }
+        if self.liquidation.is_some() {
+            log!("A liquidation is in flight, refunding");
+            return amount;
+        }
+        if !self.expect_asset(&asset_id).deposits_enabled {
+            log!("Deposits of {} are disabled, refunding", asset_id);
+            return amount;
+        }
+
+        if msg == PROPORTIONAL_DEPOSIT_MSG {
+            let key = (sender_id, asset_id);
+            let pending = self.pending_deposits.get(&key).unwrap_or(U128(0));
+            self.pending_deposits
+                .insert(&key, &U128(pending.0 + amount.0));
+            return U128(0);
+        }
+        require!(msg.is_empty(), "Unknown deposit msg");
+
+        // only the part that fits under the balance cap is deposited
+        let holding = self.expect_asset(&asset_id);
+        let accepted = amount.0.min(Self::room_under_cap(&holding));
+        if accepted == 0 {
+            log!("{} is at its balance cap, refunding", asset_id);
+            return amount;
+        }
+
+        let value = Self::value_of(&holding, accepted);
+        if value < self.min_deposit_value.0 {
+            log!("Deposit below the minimum, refunding");
+            return amount;
+        }
+
+        let minted = self.internal_deposit(&sender_id, &asset_id, accepted);
+        log!(
+            "Deposited {} {}, minted {} shares",
+            accepted,
+            asset_id,
+            minted
+        );
+        U128(amount.0 - accepted)
+    }
+
+    // Why `amount` wrapped NEAR can't be deposited for `account_id` right now, if it can't.
+    // Checked both before wrapping and once the wNEAR has arrived, since other calls can run in
+    // between. Expects the management fee to be accrued already.
+    fn wrapped_near_deposit_error(
+        &self,
+        account_id: &AccountId,
+        wrap_near_id: &AccountId,
+        amount: u128,
+    ) -> Option<String> {
+        let holding = self.assets.get(wrap_near_id);
+        let error = if self.paused {
+            "Contract is paused".to_string()
+        } else if self.in_unpause_grace() {
+            "Contract is in its post-unpause grace period".to_string()
+        } else if self.winding_down {
+            "Fund is winding down".to_string()
+        } else if self.liquidation.is_some() {
+            "A liquidation is in flight".to_string()
+        } else if self.shares.get(account_id).is_none() {
+            format!("The account {} is not registered", account_id)
+        } else if let Some(holding) = holding {
+            let value = Self::value_of(&holding, amount);
+            let minted = self.internal_shares_for_value(value);
+            if !holding.deposits_enabled {
+                "Deposits of wrapped NEAR are disabled".to_string()
+            } else if holding.last_price.0 == 0 {
+                "Asset has no price".to_string()
+            } else if amount > Self::room_under_cap(&holding) {
+                format!("Deposit would exceed the balance cap for {}", wrap_near_id)
+            } else if value < self.min_deposit_value.0 {
+                "Deposit below the minimum".to_string()
+            } else if minted == 0 {
+                "Deposit too small to mint shares".to_string()
+            } else if self.max_share_supply.is_some_and(|cap| {
+                self.total_shares.0 + self.swept_orphan_shares() + minted > cap.0
+            }) {
+                "Deposit would exceed the share supply cap".to_string()
+            } else {
+                return None;
+            }
+        } else {
+            format!("Unknown asset {}", wrap_near_id)
+        };
+        Some(error)
+    }
+
+    // Assets in `updates` the fund doesn't hold yet
+    fn new_assets(&self, updates: &[AssetWeight]) -> Vec<AssetId> {
+        updates
+            .iter()
+            .map(|u| u.asset_address.clone())
+            .filter(|asset_id| self.assets.get(asset_id).is_none())
+            .collect()
+    }
+
+    // How much more of the asset the fund accepts before hitting its balance cap
+    fn room_under_cap(holding: &AssetHolding) -> u128 {
+        holding
+            .max_balance
+            .map_or(u128::MAX, |cap| cap.0.saturating_sub(holding.balance.0))
+    }
+
+    // Burns `shares` from `account_id` and takes its pro rata part of every asset, less the exit
+    // fee, off the fund's books. Returns the amounts owed per asset.
+    fn internal_redeem(&mut self, account_id: &AccountId, shares: u128) -> Vec<(AssetId, U128)> {
+        self.assert_not_paused();
+        require!(shares > 0, "Nothing to redeem");
+        self.assert_unlocked(account_id);
+        self.assert_no_liquidation();
+        self.internal_accrue_management_fee();
+
+        let total_shares = self.total_shares.0;
+        self.internal_burn_shares(account_id, shares);
+
+        let holdings: Vec<(AssetId, AssetHolding)> = self.assets.iter().collect();
+        let mut amounts: Vec<u128> = holdings
+            .iter()
+            .map(|(_, holding)| Self::mul_div(holding.balance.0, shares, total_shares))
+            .collect();
+        if self.dust_policy == DustPolicy::SendWithLargest {
+            Self::add_dust_to_largest(&holdings, &mut amounts, shares, total_shares);
+        }
+
+        let exit_fee_bps = u128::from(self.exit_fee_bps.0);
+        let mut payouts = vec![];
+        let mut fee_value = 0;
+        for ((asset_id, mut holding), amount) in holdings.into_iter().zip(amounts) {
+            let fee = amount * exit_fee_bps / 10000;
+            fee_value += Self::value_of(&holding, fee);
+            let amount = amount - fee;
+            if amount == 0 {
+                continue;
+            }
+
+            holding.balance = U128(holding.balance.0 - amount);
+            self.assets.insert(&asset_id, &holding);
+            payouts.push((asset_id, U128(amount)));
+        }
+        self.add_fees_collected(fee_value);
+        payouts
+    }
+
+    // Tops up the largest payout (by value) with the value lost to rounding down every payout
+    fn add_dust_to_largest(
+        holdings: &[(AssetId, AssetHolding)],
+        amounts: &mut [u128],
+        shares: u128,
+        total_shares: u128,
+    ) {
+        let nav: u128 = holdings.iter().map(|(_, h)| Self::asset_value(h)).sum();
+        let owed = Self::mul_div(nav, shares, total_shares);
+        let paid: u128 = holdings
+            .iter()
+            .zip(amounts.iter())
+            .map(|((_, h), amount)| Self::value_of(h, *amount))
+            .sum();
+        let Some((largest, (_, holding))) = holdings
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, h))| h.last_price.0 > 0)
+            .max_by_key(|(i, (_, h))| Self::value_of(h, amounts[*i]))
+        else {
+            return;
+        };
+
+        let dust = Self::mul_div(
+            owed.saturating_sub(paid),
+            10u128.pow(u32::from(holding.decimals)),
+            holding.last_price.0,
+        );
+        amounts[largest] = (amounts[largest] + dust).min(holding.balance.0);
+    }
+
+    // Pays out a redemption whose shares are already burned; `on_redemption_transfer` queues it
+    // again for the redeemer if the transfer fails
+    fn transfer_redemption(
+        &self,
+        account: &AccountId,
+        asset_id: &AssetId,
+        amount: U128,
+    ) -> Promise {
+        ext_ft_core::ext(asset_id.clone())
+            .with_attached_deposit(NearToken::from_yoctonear(1))
+            .with_static_gas(GAS_FOR_FT_TRANSFER)
+            .ft_transfer(account.clone(), amount, None)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_TRANSFER)
+                    .on_redemption_transfer(account.clone(), asset_id.clone(), amount),
+            )
+    }
+
+    // Sends fund-held tokens out (only `emergency_withdraw`, where nothing is owed to anyone);
+    // `on_asset_transfer` restores the balance if the transfer fails
+    fn transfer_asset(&self, asset_id: &AssetId, receiver_id: &AccountId, amount: u128) -> Promise {
+        ext_ft_core::ext(asset_id.clone())
+            .with_attached_deposit(NearToken::from_yoctonear(1))
+            .with_static_gas(GAS_FOR_FT_TRANSFER)
+            .ft_transfer(receiver_id.clone(), U128(amount), None)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_TRANSFER)
+                    .on_asset_transfer(asset_id.clone(), U128(amount)),
+            )
+    }
+
+    // Shares worth `value` at the current NAV; the first deposit mints at `quote_units_per_share`
+    fn shares_for_value(&self, value: u128) -> u128 {
+        require!(
+            self.total_shares.0 == 0 || self.internal_nav() > 0,
+            "NAV is zero with outstanding shares"
+        );
+        let minted = self.internal_shares_for_value(value);
+        require!(minted > 0, "Deposit too small to mint shares");
+        minted
+    }
+
+    // `shares_for_value` without the checks: zero when nothing would be minted
+    fn internal_shares_for_value(&self, value: u128) -> u128 {
+        if self.total_shares.0 == 0 {
+            return value / self.quote_units_per_share();
+        }
+        let nav = self.internal_nav();
+        if nav == 0 {
+            return 0;
+        }
+        Self::mul_div(value, self.total_shares.0, nav)
+    }
+
+    fn measure_account_storage_usage(&mut self) {
+        let initial_storage_usage = env::storage_usage();
+        let tmp_account_id: AccountId = "a".repeat(64).parse().unwrap();
+        self.shares.insert(&tmp_account_id, &U128(0));
+        self.last_deposit_at.insert(&tmp_account_id, &U64(0));
+        self.account_storage_usage = env::storage_usage() - initial_storage_usage;
+        self.shares.remove(&tmp_account_id);
+        self.last_deposit_at.remove(&tmp_account_id);
+    }
+
+    fn internal_storage_balance_of(&self, account_id: &AccountId) -> Option<StorageBalance> {
+        self.shares.get(account_id).map(|_| StorageBalance {
+            total: self.storage_balance_bounds().min,
+            available: NearToken::from_near(0),
+        })
+    }
+
+    /// Mints shares to a storage-registered account.
+    pub fn internal_mint_shares(&mut self, account_id: &AccountId, amount: u128) {
+        let balance = self.shares.get(account_id).unwrap_or_else(|| {
+            env::panic_str(&format!("The account {} is not registered", account_id))
+        });
+        self.shares.insert(account_id, &U128(balance.0 + amount));
+        self.total_shares = U128(self.total_shares.0 + amount);
+    }
+
+    /// Burns shares from a storage-registered account.
+    pub fn internal_burn_shares(&mut self, account_id: &AccountId, amount: u128) {
+        let balance = self.shares.get(account_id).unwrap_or_else(|| {
+            env::panic_str(&format!("The account {} is not registered", account_id))
+        });
+        require!(balance.0 >= amount, "Insufficient shares");
+        self.shares.insert(account_id, &U128(balance.0 - amount));
+        self.total_shares = U128(self.total_shares.0 - amount);
+    }
+}
+
+#[near]
+impl FungibleTokenMetadataProvider for IndexFund {
+    fn ft_metadata(&self) -> FungibleTokenMetadata {
+        let metadata = self.fund_metadata();
+        FungibleTokenMetadata {
+            spec: FT_METADATA_SPEC.to_string(),
+            name: metadata.name,
+            symbol: metadata.symbol,
+            icon: metadata.icon,
+            reference: metadata.reference,
+            reference_hash: None,
+            decimals: self.share_decimals,
+        }
+    }
+}
+
+// Deposits arrive as NEP-141 `ft_transfer_call`s from the asset's token contract
+#[near]
+impl FungibleTokenReceiver for IndexFund {
+    fn ft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        amount: U128,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        // Guard the deposit path against a malicious token calling back into it
+        require!(!self.processing_transfer, "Reentrant ft_on_transfer");
+        self.processing_transfer = true;
+        let unused = self.internal_ft_on_transfer(sender_id, amount, msg);
+        self.processing_transfer = false;
+        PromiseOrValue::Value(unused)
+    }
+}
+
+// NEP-145: accounts must register (and pay for their storage) before they can hold shares
+#[near]
+impl StorageManagement for IndexFund {
+    // Share holders have a fixed storage footprint, so `registration_only` makes no difference.
+    #[allow(unused_variables)]
+    #[payable]
+    fn storage_deposit(
+        &mut self,
+        account_id: Option<AccountId>,
+        registration_only: Option<bool>,
+    ) -> StorageBalance {
+        let amount = env::attached_deposit();
+        let account_id = account_id.unwrap_or_else(env::predecessor_account_id);
+        if self.shares.get(&account_id).is_some() {
+            log!("The account is already registered, refunding the deposit");
+            if amount > NearToken::from_near(0) {
+                Promise::new(env::predecessor_account_id()).transfer(amount);
+            }
+        } else {
+            let min_balance = self.storage_balance_bounds().min;
+            require!(
+                amount >= min_balance,
+                "The attached deposit is less than the minimum storage balance"
+            );
+
+            self.shares.insert(&account_id, &U128(0));
+            let refund = amount.saturating_sub(min_balance);
+            if refund > NearToken::from_near(0) {
+                Promise::new(env::predecessor_account_id()).transfer(refund);
+            }
+        }
+        self.internal_storage_balance_of(&account_id).unwrap()
+    }
+
+    // min == max, so there is never an available balance to withdraw
+    #[payable]
+    fn storage_withdraw(&mut self, amount: Option<NearToken>) -> StorageBalance {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let storage_balance = self
+            .internal_storage_balance_of(&account_id)
+            .unwrap_or_else(|| {
+                env::panic_str(&format!("The account {} is not registered", account_id))
+            });
+        if let Some(amount) = amount {
+            require!(
+                amount == NearToken::from_near(0),
+                "The amount is greater than the available storage balance"
+            );
+        }
+        storage_balance
+    }
+
+    #[payable]
+    fn storage_unregister(&mut self, force: Option<bool>) -> bool {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let Some(balance) = self.shares.get(&account_id) else {
+            log!("The account {} is not registered", account_id);
+            return false;
+        };
+        // fees are minted to it, and `set_fee_recipient` registered it without a deposit
+        require!(
+            self.fee_recipient.as_ref() != Some(&account_id),
+            "The fee recipient can't unregister"
+        );
+        require!(
+            balance.0 == 0 || force.unwrap_or(false),
+            "Can't unregister the account with the positive balance without force"
+        );
+        // the burned shares still owe the fee for the time they were outstanding
+        self.internal_accrue_management_fee();
+        self.shares.remove(&account_id);
+        self.last_deposit_at.remove(&account_id);
+        self.total_shares = U128(self.total_shares.0 - balance.0);
+        Promise::new(account_id).transfer(
+            self.storage_balance_bounds()
+                .min
+                .saturating_add(NearToken::from_yoctonear(1)),
+        );
+        true
+    }
+
+    fn storage_balance_bounds(&self) -> StorageBalanceBounds {
+        let required_storage_balance =
+            env::storage_byte_cost().saturating_mul(self.account_storage_usage.into());
+        StorageBalanceBounds {
+            min: required_storage_balance,
+            max: Some(required_storage_balance),
+        }
+    }
+
+    fn storage_balance_of(&self, account_id: AccountId) -> Option<StorageBalance> {
+        self.internal_storage_balance_of(&account_id)
+    }
+}
+
+/*
+ * =====================================================================
+ * --------------------------------Tests--------------------------------
+ * =====================================================================
+ */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::{get_logs, VMContextBuilder};
+    use near_sdk::testing_env;
+    use std::str::FromStr;
+
+    // (receiver, method) of every function call scheduled so far
+    fn created_calls() -> Vec<(AccountId, String)> {
+        near_sdk::test_utils::get_created_receipts()
+            .into_iter()
+            .flat_map(|receipt| {
+                let receiver_id = receipt.receiver_id;
+                receipt
+                    .actions
+                    .into_iter()
+                    .filter_map(move |action| match action {
+                        near_sdk::mock::MockAction::FunctionCallWeight { method_name, .. } => {
+                            Some((receiver_id.clone(), String::from_utf8(method_name).unwrap()))
+                        }
+                        _ => None,
+                    })
+            })
+            .collect()
+    }
+
+    fn get_context(predecessor: AccountId) -> VMContextBuilder {
+        let mut builder = VMContextBuilder::new();
+        builder.predecessor_account_id(predecessor);
+        builder.block_timestamp(100);
+        builder
+    }
+
+    #[test]
+    fn test_default_index_fund() {
+        let contract = IndexFund::with_defaults();
+        assert!(contract.curator_address.is_none());
+        assert_eq!(contract.last_rebalance, U64(0));
+        assert_eq!(contract.rebalance_interval, U64(86400));
+        assert_eq!(contract.get_assets().len(), 0);
+    }
+
+    // What a generated entry point runs on: the stored state, or `Default` if nothing is stored
+    fn entry_point_state() -> IndexFund {
+        env::state_read().unwrap_or_default()
+    }
+
+    #[test]
+    #[should_panic(expected = "The contract is not initialized")]
+    fn test_update_weights_uninitialized() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+        testing_env!(get_context(curator).build());
+
+        entry_point_state().update_weights(vec![AssetWeight {
+            weight: U64(10000),
+            asset_address: asset,
+        }]);
+    }
+
+    #[test]
+    #[should_panic(expected = "The contract is not initialized")]
+    fn test_ft_on_transfer_uninitialized() {
+        let investor = AccountId::from_str("investor.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+        testing_env!(get_context(asset).build());
+
+        let _ = entry_point_state().ft_on_transfer(investor, U128(100), String::new());
+    }
+
+    #[test]
+    #[should_panic(expected = "The contract is not initialized")]
+    fn test_redeem_uninitialized() {
+        let investor = AccountId::from_str("investor.near").unwrap();
+        let mut context = get_context(investor);
+        context.attached_deposit(NearToken::from_yoctonear(1));
+        testing_env!(context.build());
+
+        entry_point_state().redeem(U128(100));
+    }
+
+    #[test]
+    fn test_update_weights() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+
+        let context = get_context(curator.clone());
+        testing_env!(context.build());
+
+        let mut contract = IndexFund::with_defaults();
+        contract.curator_address = Some(curator);
+
+        let updates = vec![
+            AssetWeight {
+                weight: U64(6000),
+                asset_address: asset1.clone(),
+            },
+            AssetWeight {
+                weight: U64(4000),
+                asset_address: asset2.clone(),
+            },
+        ];
+
+        contract.update_weights(updates);
+
+        let weights = contract.get_weights();
+        assert_eq!(weights.len(), 2);
+
+        let asset1_weight = weights
+            .iter()
+            .find(|w| w.asset_address == asset1)
+            .expect("Asset1 not found");
+        assert_eq!(asset1_weight.weight, U64(6000));
+
+        let asset2_weight = weights
+            .iter()
+            .find(|w| w.asset_address == asset2)
+            .expect("Asset2 not found");
+        assert_eq!(asset2_weight.weight, U64(4000));
+    }
+
+    #[test]
+    #[should_panic(expected = "curator not registered")]
+    fn test_update_weights_without_curator() {
+        let mut contract = IndexFund::with_defaults();
+        let asset = AccountId::from_str("asset.near").unwrap();
+
+        contract.update_weights(vec![AssetWeight {
+            weight: U64(10000),
+            asset_address: asset,
+        }]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Unauthorized")]
+    fn test_update_weights_unauthorized() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let unauthorized = AccountId::from_str("unauthorized.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+
+        let context = get_context(unauthorized);
+        testing_env!(context.build());
+
+        let mut contract = IndexFund::with_defaults();
+        contract.curator_address = Some(curator);
+
+        contract.update_weights(vec![AssetWeight {
+            weight: U64(10000),
+            asset_address: asset,
+        }]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Final weights must sum to 100%")]
+    fn test_update_weights_invalid_sum() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+
+        let context = get_context(curator.clone());
+        testing_env!(context.build());
+
+        let mut contract = IndexFund::with_defaults();
+        contract.curator_address = Some(curator);
+
+        contract.update_weights(vec![AssetWeight {
+            weight: U64(5000), // Only 50% instead of required 100%
+            asset_address: asset,
+        }]);
+    }
+
+    // Builds a default fund owned by `curator` (who stays the predecessor) with the given weights
+    fn fund_with_weights(curator: &AccountId, weights: &[(&AccountId, u64)]) -> IndexFund {
+        testing_env!(get_context(curator.clone()).build());
+
+        let mut contract = IndexFund::with_defaults();
+        contract.curator_address = Some(curator.clone());
+        contract.update_weights(
+            weights
+                .iter()
+                .map(|(asset_id, weight)| AssetWeight {
+                    weight: U64(*weight),
+                    asset_address: (*asset_id).clone(),
+                })
+                .collect(),
+        );
+        contract
+    }
+
+    fn set_prices(contract: &mut IndexFund, prices: &[(&AccountId, u128)]) {
+        for (asset_id, price) in prices {
+            let mut holding = contract.assets.get(asset_id).expect("Asset not found");
+            holding.last_price = U128(*price);
+            contract.assets.insert(asset_id, &holding);
+        }
+    }
+
+    fn fund_with_frozen_prices(owner: &AccountId, oracle: &AccountId) -> IndexFund {
+        let asset = AccountId::from_str("asset.near").unwrap();
+
+        let mut contract = fund_with_weights(owner, &[(&asset, 10000)]);
+        contract.owner_id = Some(owner.clone());
+        contract.set_oracle(oracle.clone());
+        contract.set_prices_frozen(true);
+        contract
+    }
+
+    #[test]
+    #[should_panic(expected = "Price updates are frozen")]
+    fn test_update_prices_frozen() {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        let oracle = AccountId::from_str("oracle.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+        let mut contract = fund_with_frozen_prices(&owner, &oracle);
+
+        testing_env!(get_context(oracle).build());
+        contract.update_prices(vec![(asset, U128(100))]);
+    }
+
+    #[test]
+    fn test_update_prices_after_unfreeze() {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        let oracle = AccountId::from_str("oracle.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+        let mut contract = fund_with_frozen_prices(&owner, &oracle);
+        contract.set_prices_frozen(false);
+
+        testing_env!(get_context(oracle).build());
+        contract.update_prices(vec![(asset.clone(), U128(100))]);
+        assert_eq!(contract.assets.get(&asset).unwrap().last_price, U128(100));
+    }
+
+    #[test]
+    fn test_update_prices() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let oracle = AccountId::from_str("oracle.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset1, 5000), (&asset2, 5000)]);
+        contract.set_oracle(oracle.clone());
+        contract.set_max_price_move_bps(U64(2000));
+        set_prices(&mut contract, &[(&asset1, 100), (&asset2, 200)]);
+
+        let mut context = get_context(oracle);
+        context.block_timestamp(500);
+        testing_env!(context.build());
+
+        // both moves are within 20%
+        contract.update_prices(vec![
+            (asset1.clone(), U128(110)),
+            (asset2.clone(), U128(180)),
+        ]);
+
+        let holding1 = contract.assets.get(&asset1).unwrap();
+        assert_eq!(holding1.last_price, U128(110));
+        assert_eq!(holding1.last_updated, U64(500));
+        assert_eq!(contract.assets.get(&asset2).unwrap().last_price, U128(180));
+    }
+
+    #[test]
+    #[should_panic(expected = "Price move too large for asset1.near")]
+    fn test_update_prices_rejects_large_move() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let oracle = AccountId::from_str("oracle.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset1, 5000), (&asset2, 5000)]);
+        contract.set_oracle(oracle.clone());
+        contract.set_max_price_move_bps(U64(2000));
+        set_prices(&mut contract, &[(&asset1, 100), (&asset2, 200)]);
+
+        testing_env!(get_context(oracle).build());
+
+        // asset2 moves within the limit and updates
+        contract.update_prices(vec![(asset2.clone(), U128(210))]);
+        assert_eq!(contract.assets.get(&asset2).unwrap().last_price, U128(210));
+
+        // asset1 doubles and is rejected
+        contract.update_prices(vec![(asset1, U128(200))]);
+    }
+
+    fn fund_with_oracle(
+        curator: &AccountId,
+        oracle: &AccountId,
+        weights: &[(&AccountId, u64)],
+    ) -> IndexFund {
+        let mut contract = fund_with_weights(curator, weights);
+        contract.set_oracle(oracle.clone());
+
+        let mut context = get_context(oracle.clone());
+        context.block_timestamp(1000);
+        testing_env!(context.build());
+        contract
+    }
+
+    #[test]
+    fn test_oracle_handoff() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let oracle = AccountId::from_str("oracle.near").unwrap();
+        let new_oracle = AccountId::from_str("new-oracle.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+
+        let mut contract = fund_with_oracle(&curator, &oracle, &[(&asset, 10000)]);
+        contract.propose_oracle(new_oracle.clone());
+        assert_eq!(contract.get_oracle(), Some(oracle));
+
+        testing_env!(get_context(new_oracle.clone()).build());
+        contract.accept_oracle();
+        assert_eq!(contract.get_oracle(), Some(new_oracle));
+        assert_eq!(contract.proposed_oracle, None);
+        contract.update_prices(vec![(asset.clone(), U128(5))]);
+        assert_eq!(contract.assets.get(&asset).unwrap().last_price, U128(5));
+    }
+
+    #[test]
+    #[should_panic(expected = "Unauthorized")]
+    fn test_accept_oracle_by_stranger() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let oracle = AccountId::from_str("oracle.near").unwrap();
+        let new_oracle = AccountId::from_str("new-oracle.near").unwrap();
+        let stranger = AccountId::from_str("stranger.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+
+        let mut contract = fund_with_oracle(&curator, &oracle, &[(&asset, 10000)]);
+        contract.propose_oracle(new_oracle);
+
+        testing_env!(get_context(stranger).build());
+        contract.accept_oracle();
+    }
+
+    #[test]
+    #[should_panic(expected = "Unauthorized")]
+    fn test_propose_oracle_unauthorized() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let oracle = AccountId::from_str("oracle.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+
+        let mut contract = fund_with_oracle(&curator, &oracle, &[(&asset, 10000)]);
+        testing_env!(get_context(curator.clone()).build());
+        contract.propose_oracle(curator);
+    }
+
+    #[test]
+    fn test_curator_history() {
+        let first = AccountId::from_str("first.near").unwrap();
+        let second = AccountId::from_str("second.near").unwrap();
+        let third = AccountId::from_str("third.near").unwrap();
+
+        let mut context = get_context(first.clone());
+        context.attached_deposit(NearToken::from_near(1));
+        testing_env!(context.build());
+        let mut contract = IndexFund::with_defaults();
+        contract.register_curator(first.clone());
+
+        for (height, (from, to)) in [(10, (&first, &second)), (20, (&second, &third))] {
+            let mut context = get_context(from.clone());
+            context.block_height(height);
+            testing_env!(context.build());
+            contract.propose_curator(to.clone());
+            testing_env!(get_context(to.clone()).block_height(height).build());
+            contract.accept_curator();
+        }
+
+        assert_eq!(contract.get_curator(), Some(third.clone()));
+        assert_eq!(
+            contract.get_curator_history(),
+            vec![
+                CuratorChange {
+                    old: None,
+                    new: first.clone(),
+                    block_height: U64(0),
+                },
+                CuratorChange {
+                    old: Some(first),
+                    new: second.clone(),
+                    block_height: U64(10),
+                },
+                CuratorChange {
+                    old: Some(second),
+                    new: third,
+                    block_height: U64(20),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Unauthorized")]
+    fn test_accept_curator_by_stranger() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let stranger = AccountId::from_str("stranger.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset, 10000)]);
+        contract.propose_curator(AccountId::from_str("new-curator.near").unwrap());
+
+        testing_env!(get_context(stranger).build());
+        contract.accept_curator();
+    }
+
+    fn fund_with_asset_oracle(feed: &AccountId) -> IndexFund {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let oracle = AccountId::from_str("oracle.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset1, 5000), (&asset2, 5000)]);
+        contract.set_oracle(oracle);
+        contract.set_asset_oracle(asset1, Some(feed.clone()));
+        testing_env!(get_context(feed.clone()).build());
+        contract
+    }
+
+    #[test]
+    fn test_asset_oracle() {
+        let feed = AccountId::from_str("feed.near").unwrap();
+        let oracle = AccountId::from_str("oracle.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+        let mut contract = fund_with_asset_oracle(&feed);
+
+        contract.update_prices(vec![(asset1.clone(), U128(10))]);
+        assert_eq!(contract.assets.get(&asset1).unwrap().last_price, U128(10));
+
+        // the fund's oracle still prices the other asset
+        testing_env!(get_context(oracle).build());
+        contract.update_prices(vec![(asset2.clone(), U128(20))]);
+        assert_eq!(contract.assets.get(&asset2).unwrap().last_price, U128(20));
+    }
+
+    #[test]
+    #[should_panic(expected = "Unauthorized")]
+    fn test_asset_oracle_other_asset() {
+        let feed = AccountId::from_str("feed.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+        let mut contract = fund_with_asset_oracle(&feed);
+
+        contract.update_prices(vec![(asset2, U128(20))]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Unauthorized")]
+    fn test_global_oracle_cannot_price_asset_with_own_oracle() {
+        let feed = AccountId::from_str("feed.near").unwrap();
+        let oracle = AccountId::from_str("oracle.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let mut contract = fund_with_asset_oracle(&feed);
+
+        testing_env!(get_context(oracle).build());
+        contract.update_prices(vec![(asset1, U128(10))]);
+    }
+
+    #[test]
+    fn test_update_prices_ts() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let oracle = AccountId::from_str("oracle.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+
+        let mut contract = fund_with_oracle(&curator, &oracle, &[(&asset1, 5000), (&asset2, 5000)]);
+        contract.update_prices_ts(vec![
+            (asset1.clone(), U128(10), U64(500)),
+            (asset2.clone(), U128(20), U64(1000)),
+        ]);
+
+        let holding1 = contract.assets.get(&asset1).unwrap();
+        assert_eq!(holding1.last_price, U128(10));
+        assert_eq!(holding1.last_updated, U64(500));
+        let holding2 = contract.assets.get(&asset2).unwrap();
+        assert_eq!(holding2.last_price, U128(20));
+        assert_eq!(holding2.last_updated, U64(1000));
+
+        // repeating the same observation time is fine
+        contract.update_prices_ts(vec![(asset1.clone(), U128(11), U64(500))]);
+        assert_eq!(contract.assets.get(&asset1).unwrap().last_price, U128(11));
+    }
+
+    #[test]
+    #[should_panic(expected = "Price older than the current one for asset1.near")]
+    fn test_update_prices_ts_out_of_order() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let oracle = AccountId::from_str("oracle.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+
+        let mut contract = fund_with_oracle(&curator, &oracle, &[(&asset1, 10000)]);
+        contract.update_prices_ts(vec![(asset1.clone(), U128(10), U64(500))]);
+        contract.update_prices_ts(vec![(asset1, U128(10), U64(499))]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Price timestamp in the future for asset1.near")]
+    fn test_update_prices_ts_future() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let oracle = AccountId::from_str("oracle.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+
+        let mut contract = fund_with_oracle(&curator, &oracle, &[(&asset1, 10000)]);
+        contract.update_prices_ts(vec![(asset1, U128(10), U64(1001))]);
+    }
+
+    #[test]
+    fn test_get_price_health() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let oracle = AccountId::from_str("oracle.near").unwrap();
+        let fresh = AccountId::from_str("fresh.near").unwrap();
+        let stale = AccountId::from_str("stale.near").unwrap();
+        let unpriced = AccountId::from_str("unpriced.near").unwrap();
+
+        let mut contract = fund_with_oracle(
+            &curator,
+            &oracle,
+            &[(&fresh, 4000), (&stale, 4000), (&unpriced, 2000)],
+        );
+        contract.update_prices_ts(vec![
+            (fresh.clone(), U128(10), U64(900)),
+            (stale.clone(), U128(20), U64(300)),
+        ]);
+        testing_env!(get_context(curator).build());
+        contract.set_max_price_age_ns(U64(500));
+
+        let mut context = get_context(oracle);
+        context.block_timestamp(1000);
+        testing_env!(context.build());
+        let health = contract.get_price_health();
+        let of = |asset_id: &AccountId| {
+            health
+                .iter()
+                .find(|h| h.asset_id == *asset_id)
+                .map(|h| (h.price, h.age_ns, h.is_stale))
+        };
+        assert_eq!(of(&fresh), Some((U128(10), U64(100), false)));
+        assert_eq!(of(&stale), Some((U128(20), U64(700), true)));
+        assert_eq!(of(&unpriced).map(|(p, _, s)| (p, s)), Some((U128(0), true)));
+    }
+
+    #[test]
+    fn test_composition_hash() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset1, 6000), (&asset2, 4000)]);
+        let hash = contract.get_composition_hash();
+        assert_eq!(hash.len(), 64);
+        assert_eq!(contract.get_composition_hash(), hash);
+
+        // price changes don't affect the composition
+        set_prices(&mut contract, &[(&asset1, 10)]);
+        assert_eq!(contract.get_composition_hash(), hash);
+
+        contract.update_weights(vec![
+            AssetWeight {
+                weight: U64(5000),
+                asset_address: asset1,
+            },
+            AssetWeight {
+                weight: U64(5000),
+                asset_address: asset2,
+            },
+        ]);
+        assert_ne!(contract.get_composition_hash(), hash);
+    }
+
+    #[test]
+    fn test_get_asset_value() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+        let unknown = AccountId::from_str("unknown.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset1, 5000), (&asset2, 5000)]);
+        contract.set_asset_decimals(asset1.clone(), 18);
+        set_prices(&mut contract, &[(&asset1, 3)]);
+        set_balances(
+            &mut contract,
+            &[(&asset1, 5_000_000_000_000_000_000), (&asset2, 100)],
+        );
+
+        // 5 whole tokens at 3 each
+        assert_eq!(contract.get_asset_value(asset1), U128(15));
+        assert_eq!(contract.get_asset_value(asset2), U128(0));
+        assert_eq!(contract.get_asset_value(unknown), U128(0));
+        assert_eq!(contract.get_nav(), U128(15));
+    }
+
+    #[test]
+    #[should_panic(expected = "Can't change decimals of a funded asset")]
+    fn test_set_asset_decimals_funded() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset, 10000)]);
+        set_balances(&mut contract, &[(&asset, 1)]);
+        contract.set_asset_decimals(asset, 6);
+    }
+
+    #[test]
+    fn test_get_drift_value() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset1, 5000), (&asset2, 5000)]);
+        set_prices(&mut contract, &[(&asset1, 3), (&asset2, 1)]);
+        set_balances(&mut contract, &[(&asset1, 100), (&asset2, 100)]);
+
+        // NAV 400, each asset targets 200
+        let drift = contract.get_drift_value();
+        assert_eq!(drift.len(), 2);
+        assert!(drift.contains(&(asset1, 100)));
+        assert!(drift.contains(&(asset2, -100)));
+    }
+
+    #[test]
+    fn test_get_shareholders() {
+        let alice = AccountId::from_str("alice.near").unwrap();
+        let bob = AccountId::from_str("bob.near").unwrap();
+        let carol = AccountId::from_str("carol.near").unwrap();
+
+        let mut contract = IndexFund::with_defaults();
+        contract.shares.insert(&alice, &U128(10));
+        contract.shares.insert(&bob, &U128(20));
+        contract.shares.insert(&carol, &U128(30));
+        assert_eq!(contract.get_shareholder_count(), 3);
+
+        let first = contract.get_shareholders(0, 2);
+        assert_eq!(first, vec![(alice, U128(10)), (bob, U128(20))]);
+        let second = contract.get_shareholders(2, 2);
+        assert_eq!(second, vec![(carol, U128(30))]);
+        assert!(contract.get_shareholders(3, 2).is_empty());
+    }
+
+    #[test]
+    fn test_verify_share_accounting() {
+        let alice = AccountId::from_str("alice.near").unwrap();
+        let bob = AccountId::from_str("bob.near").unwrap();
+
+        let mut contract = IndexFund::with_defaults();
+        assert!(contract.verify_share_accounting());
+
+        contract.shares.insert(&alice, &U128(0));
+        contract.shares.insert(&bob, &U128(0));
+        contract.internal_mint_shares(&alice, 100);
+        contract.internal_mint_shares(&bob, 50);
+        contract.internal_burn_shares(&alice, 30);
+        assert!(contract.verify_share_accounting());
+
+        contract.shares.insert(&bob, &U128(60));
+        assert!(!contract.verify_share_accounting());
+    }
+
+    #[test]
+    fn test_set_weights_from_balances() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+        let asset3 = AccountId::from_str("asset3.near").unwrap();
+
+        let mut contract = fund_with_weights(
+            &curator,
+            &[(&asset1, 4000), (&asset2, 3000), (&asset3, 3000)],
+        );
+        set_prices(&mut contract, &[(&asset1, 1), (&asset2, 1), (&asset3, 1)]);
+        set_balances(
+            &mut contract,
+            &[(&asset1, 200), (&asset2, 100), (&asset3, 100)],
+        );
+
+        contract.set_weights_from_balances();
+        assert_eq!(contract.assets.get(&asset1).unwrap().weight, U64(5000));
+        assert_eq!(contract.assets.get(&asset2).unwrap().weight, U64(2500));
+        assert_eq!(contract.assets.get(&asset3).unwrap().weight, U64(2500));
+
+        set_balances(
+            &mut contract,
+            &[(&asset1, 100), (&asset2, 100), (&asset3, 101)],
+        );
+        contract.set_weights_from_balances();
+        assert_eq!(contract.assets.get(&asset1).unwrap().weight, U64(3322));
+        assert_eq!(contract.assets.get(&asset2).unwrap().weight, U64(3322));
+        assert_eq!(contract.assets.get(&asset3).unwrap().weight, U64(3356));
+    }
+
+    #[test]
+    #[should_panic(expected = "Weight out of bounds for asset1.near")]
+    fn test_set_weights_from_balances_respects_bounds() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset1, 5000), (&asset2, 5000)]);
+        contract.set_asset_weight_bounds(asset1.clone(), U64(0), U64(6000));
+        set_prices(&mut contract, &[(&asset1, 1), (&asset2, 1)]);
+        // drifted to 70%, past the 60% cap
+        set_balances(&mut contract, &[(&asset1, 700), (&asset2, 300)]);
+
+        contract.set_weights_from_balances();
+    }
+
+    #[test]
+    fn test_rebalance_weights_by_market_cap() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let oracle = AccountId::from_str("oracle.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+        let asset3 = AccountId::from_str("asset3.near").unwrap();
+        let delisted = AccountId::from_str("delisted.near").unwrap();
+
+        let mut contract = fund_with_oracle(
+            &curator,
+            &oracle,
+            &[
+                (&asset1, 2500),
+                (&asset2, 2500),
+                (&asset3, 2500),
+                (&delisted, 2500),
+            ],
+        );
+        contract.update_market_caps(vec![
+            (asset1.clone(), U128(600_000)),
+            (asset2.clone(), U128(300_000)),
+            (asset3.clone(), U128(100_000)),
+        ]);
+
+        testing_env!(get_context(curator).build());
+        contract.rebalance_weights_by_market_cap();
+        let weight_of = |asset_id: &AccountId| contract.assets.get(asset_id).unwrap().weight;
+        assert_eq!(weight_of(&asset1), U64(6000));
+        assert_eq!(weight_of(&asset2), U64(3000));
+        assert_eq!(weight_of(&asset3), U64(1000));
+        assert_eq!(weight_of(&delisted), U64(0));
+    }
+
+    #[test]
+    fn test_update_weights_emits_asset_added_for_new_assets() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset1, 10000)]);
+        testing_env!(get_context(curator).build());
+
+        contract.update_weights(vec![
+            AssetWeight {
+                weight: U64(7000),
+                asset_address: asset1,
+            },
+            AssetWeight {
+                weight: U64(3000),
+                asset_address: asset2,
+            },
+        ]);
+
+        let events: Vec<String> = get_logs()
+            .into_iter()
+            .filter(|log| log.starts_with("EVENT_JSON:"))
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                r#"EVENT_JSON:{"standard":"index_fund","version":"1.0.0","event":"asset_added","data":{"asset_address":"asset2.near","initial_weight":"3000"}}"#
+            ]
+        );
+    }
+
+    #[test]
+    fn test_get_unpriced_assets() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+        let asset3 = AccountId::from_str("asset3.near").unwrap();
+        let unweighted = AccountId::from_str("unweighted.near").unwrap();
+
+        let mut contract = fund_with_weights(
+            &curator,
+            &[
+                (&asset1, 5000),
+                (&asset2, 3000),
+                (&asset3, 2000),
+                (&unweighted, 0),
+            ],
+        );
+        set_prices(&mut contract, &[(&asset1, 10), (&asset3, 5)]);
+
+        assert_eq!(contract.get_unpriced_assets(), vec![asset2]);
+    }
+
+    #[test]
+    fn test_export_state() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let assets: Vec<AccountId> = ["e.near", "c.near", "a.near", "d.near", "b.near"]
+            .iter()
+            .map(|id| AccountId::from_str(id).unwrap())
+            .collect();
+
+        let mut contract = fund_with_weights(
+            &curator,
+            &[
+                (&assets[0], 1000),
+                (&assets[1], 2000),
+                (&assets[2], 3000),
+                (&assets[3], 2500),
+                (&assets[4], 1500),
+            ],
+        );
+        set_prices(&mut contract, &[(&assets[2], 7)]);
+
+        let mut exported = vec![];
+        let mut cursor = Some(0);
+        while let Some(from_index) = cursor {
+            let chunk = contract.export_state(from_index, 2);
+            assert_eq!(chunk.total_assets, 5);
+            assert!(chunk.holdings.len() <= 2);
+            exported.extend(chunk.holdings);
+            cursor = chunk.next_index;
+        }
+
+        let ids: Vec<&str> = exported.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(ids, vec!["a.near", "b.near", "c.near", "d.near", "e.near"]);
+        for (asset_id, holding) in exported.iter() {
+            let stored = contract.assets.get(asset_id).unwrap();
+            assert_eq!(holding.weight, stored.weight);
+            assert_eq!(holding.last_price, stored.last_price);
+        }
+        assert_eq!(exported[0].1.last_price, U128(7));
+    }
+
+    #[test]
+    fn test_get_weight_denominator() {
+        let contract = IndexFund::with_defaults();
+        assert_eq!(contract.get_weight_denominator(), U64(WEIGHT_DENOMINATOR));
+        assert_eq!(contract.get_weight_denominator(), U64(10000));
+    }
+
+    #[test]
+    fn test_set_asset_label() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let usdc = AccountId::from_str("usdc.near").unwrap();
+        let wbtc = AccountId::from_str("wbtc.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&usdc, 5000), (&wbtc, 5000)]);
+        contract.set_asset_label(usdc.clone(), Some("stablecoin".to_string()));
+
+        let holdings = contract.get_holdings();
+        let label_of = |asset_id: &AccountId| {
+            holdings
+                .iter()
+                .find(|(id, _)| id == asset_id)
+                .and_then(|(_, h)| h.label.clone())
+        };
+        assert_eq!(label_of(&usdc), Some("stablecoin".to_string()));
+        assert_eq!(label_of(&wbtc), None);
+    }
+
+    fn fund_with_reporter(reporter: &AccountId) -> IndexFund {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+
+        let mut contract = fund_with_weights(&owner, &[(&asset, 10000)]);
+        contract.owner_id = Some(owner);
+        contract.set_reporter(Some(reporter.clone()));
+        testing_env!(get_context(reporter.clone()).build());
+        contract
+    }
+
+    #[test]
+    fn test_reporter_sets_presentation() {
+        let reporter = AccountId::from_str("reporter.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+        let mut contract = fund_with_reporter(&reporter);
+
+        contract.set_asset_label(asset.clone(), Some("stablecoin".to_string()));
+        contract.set_fund_metadata(FundMetadata {
+            name: "Blue Chips".to_string(),
+            symbol: "BLUE".to_string(),
+            icon: Some("data:image/svg+xml,<svg/>".to_string()),
+            reference: None,
+        });
+
+        assert_eq!(
+            contract.assets.get(&asset).unwrap().label,
+            Some("stablecoin".to_string())
+        );
+        let metadata = contract.ft_metadata();
+        assert_eq!(metadata.symbol, "BLUE");
+        assert_eq!(metadata.icon, Some("data:image/svg+xml,<svg/>".to_string()));
+    }
+
+    #[test]
+    fn test_get_index_listing() {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        let usdc = AccountId::from_str("usdc.near").unwrap();
+        let wbtc = AccountId::from_str("wbtc.near").unwrap();
+
+        let mut contract = fund_with_weights(&owner, &[(&usdc, 6000), (&wbtc, 4000)]);
+        contract.owner_id = Some(owner);
+        set_prices(&mut contract, &[(&usdc, 1), (&wbtc, 1)]);
+        set_balances(&mut contract, &[(&usdc, 500), (&wbtc, 500)]);
+        contract.set_asset_label(usdc.clone(), Some("stablecoin".to_string()));
+        let metadata = FundMetadata {
+            name: "Blue Chips".to_string(),
+            symbol: "BLUE".to_string(),
+            icon: None,
+            reference: Some("https://example.com/blue.json".to_string()),
+        };
+        contract.set_fund_metadata(metadata.clone());
+
+        let listing = contract.get_index_listing();
+        assert_eq!(listing.metadata, metadata);
+        assert_eq!(listing.constituents.len(), 2);
+        assert!(listing.constituents.contains(&IndexConstituent {
+            asset_id: usdc,
+            label: Some("stablecoin".to_string()),
+            weight: U64(6000),
+            value_bps: U64(5000),
+        }));
+        assert!(listing.constituents.contains(&IndexConstituent {
+            asset_id: wbtc,
+            label: None,
+            weight: U64(4000),
+            value_bps: U64(5000),
+        }));
+    }
+
+    #[test]
+    #[should_panic(expected = "Unauthorized")]
+    fn test_reporter_cannot_update_weights() {
+        let reporter = AccountId::from_str("reporter.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+        let mut contract = fund_with_reporter(&reporter);
+
+        contract.update_weights(vec![AssetWeight {
+            weight: U64(10000),
+            asset_address: asset,
+        }]);
+    }
+
+    fn validated_update(asset1: &AccountId, asset2: &AccountId) -> (IndexFund, Vec<AssetWeight>) {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        testing_env!(get_context(curator.clone()).build());
+        let mut contract = IndexFund::with_defaults();
+        contract.curator_address = Some(curator);
+        contract.set_validate_assets_on_add(true);
+
+        let updates = vec![
+            AssetWeight {
+                weight: U64(5000),
+                asset_address: asset1.clone(),
+            },
+            AssetWeight {
+                weight: U64(5000),
+                asset_address: asset2.clone(),
+            },
+        ];
+        // deferred until the tokens answer
+        contract.update_weights(updates.clone());
+        assert_eq!(contract.get_assets().len(), 0);
+        (contract, updates)
+    }
+
+    #[test]
+    fn test_validate_assets_on_add() {
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+        let (mut contract, updates) = validated_update(&asset1, &asset2);
+
+        testing_env!(
+            get_context(env::current_account_id()).build(),
+            near_sdk::test_vm_config(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![
+                PromiseResult::Successful(vec![]),
+                PromiseResult::Successful(vec![])
+            ],
+        );
+        let curator = AccountId::from_str("curator.near").unwrap();
+        assert!(contract.on_assets_validated(curator, updates));
+        assert_eq!(contract.assets.get(&asset2).unwrap().weight, U64(5000));
+    }
+
+    #[test]
+    #[should_panic(expected = "Final weights must sum to 100%")]
+    fn test_validate_assets_on_add_checks_weights_first() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+
+        testing_env!(get_context(curator.clone()).build());
+        let mut contract = IndexFund::with_defaults();
+        contract.curator_address = Some(curator);
+        contract.set_validate_assets_on_add(true);
+        contract.update_weights(vec![AssetWeight {
+            weight: U64(9000),
+            asset_address: asset,
+        }]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Contract is paused")]
+    fn test_validate_assets_on_add_paused_meanwhile() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+        let (mut contract, updates) = validated_update(&asset1, &asset2);
+        contract.pause();
+
+        testing_env!(
+            get_context(env::current_account_id()).build(),
+            near_sdk::test_vm_config(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![
+                PromiseResult::Successful(vec![]),
+                PromiseResult::Successful(vec![])
+            ],
+        );
+        contract.on_assets_validated(curator, updates);
+    }
+
+    #[test]
+    fn test_validate_assets_on_add_unknown_token() {
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let typo = AccountId::from_str("asset2.nera").unwrap();
+        let (mut contract, updates) = validated_update(&asset1, &typo);
+
+        testing_env!(
+            get_context(env::current_account_id()).build(),
+            near_sdk::test_vm_config(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(vec![]), PromiseResult::Failed],
+        );
+        let curator = AccountId::from_str("curator.near").unwrap();
+        assert!(!contract.on_assets_validated(curator, updates));
+        assert_eq!(contract.get_assets().len(), 0);
+    }
+
+    #[test]
+    fn test_register_assets() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let assets: Vec<AccountId> = ["usdc.near", "wbtc.near", "weth.near"]
+            .iter()
+            .map(|id| AccountId::from_str(id).unwrap())
+            .collect();
+
+        testing_env!(get_context(curator.clone()).build());
+        let mut contract = IndexFund::with_defaults();
+        contract.curator_address = Some(curator);
+        contract.register_assets(
+            assets
+                .iter()
+                .zip([6, 8, 18])
+                .map(|(asset_id, decimals)| AssetRegistration {
+                    asset_id: asset_id.clone(),
+                    decimals,
+                    label: Some("crypto".to_string()),
+                })
+                .collect(),
+        );
+        assert_eq!(contract.get_assets().len(), 3);
+        assert!(contract.get_weights().iter().all(|w| w.weight == U64(0)));
+
+        contract.update_weights(vec![
+            AssetWeight {
+                weight: U64(5000),
+                asset_address: assets[0].clone(),
+            },
+            AssetWeight {
+                weight: U64(3000),
+                asset_address: assets[1].clone(),
+            },
+            AssetWeight {
+                weight: U64(2000),
+                asset_address: assets[2].clone(),
+            },
+        ]);
+        let holding = contract.assets.get(&assets[1]).unwrap();
+        assert_eq!(holding.weight, U64(3000));
+        assert_eq!(holding.decimals, 8);
+        assert_eq!(holding.label, Some("crypto".to_string()));
+        assert_eq!(contract.get_assets().len(), 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "usdc.near is already registered")]
+    fn test_register_assets_twice() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let usdc = AccountId::from_str("usdc.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&usdc, 10000)]);
+        contract.register_assets(vec![AssetRegistration {
+            asset_id: usdc,
+            decimals: 6,
+            label: None,
+        }]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Label too long")]
+    fn test_set_asset_label_too_long() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset, 10000)]);
+        contract.set_asset_label(asset, Some("x".repeat(MAX_LABEL_LEN + 1)));
+    }
+
+    #[test]
+    fn test_get_assets_storage_info() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let assets: Vec<AccountId> = ["a.near", "b.near", "c.near"]
+            .iter()
+            .map(|id| AccountId::from_str(id).unwrap())
+            .collect();
+
+        let contract = fund_with_weights(
+            &curator,
+            &[(&assets[0], 4000), (&assets[1], 3000), (&assets[2], 3000)],
+        );
+        let info = contract.get_assets_storage_info();
+        assert_eq!(info.count, 3);
+        assert!(info.approx_bytes > 0);
+    }
+
+    #[test]
+    fn test_get_assets_storage_info_matches_storage_usage() {
+        let asset = AccountId::from_str("asset.near").unwrap();
+        testing_env!(get_context(asset.clone()).build());
+        let mut contract = IndexFund::with_defaults();
+
+        let before = env::storage_usage();
+        contract
+            .assets
+            .insert(&asset, &IndexFund::new_holding(U64(10000)));
+        assert_eq!(
+            contract.get_assets_storage_info().approx_bytes,
+            env::storage_usage() - before
+        );
+    }
+
+    #[test]
+    fn test_is_asset() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+        let other = AccountId::from_str("other.near").unwrap();
+
+        let contract = fund_with_weights(&curator, &[(&asset, 10000)]);
+        assert!(contract.is_asset(asset));
+        assert!(!contract.is_asset(other));
+    }
+
+    #[test]
+    fn test_get_weights_sorted_seeded() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let big = AccountId::from_str("big.near").unwrap();
+        let tied: Vec<AccountId> = ["a.near", "b.near", "c.near", "d.near"]
+            .iter()
+            .map(|id| AccountId::from_str(id).unwrap())
+            .collect();
+
+        let mut contract = fund_with_weights(
+            &curator,
+            &[
+                (&big, 6000),
+                (&tied[0], 1000),
+                (&tied[1], 1000),
+                (&tied[2], 1000),
+                (&tied[3], 1000),
+            ],
+        );
+        let expected_ties = |seed: u64| {
+            let mut ids = tied.clone();
+            ids.sort_by_key(|id| env::keccak256(&[id.as_bytes(), &seed.to_le_bytes()].concat()));
+            ids
+        };
+        let order = |contract: &IndexFund| {
+            contract
+                .get_weights_sorted_seeded()
+                .into_iter()
+                .map(|w| w.asset_address)
+                .collect::<Vec<_>>()
+        };
+
+        let seeded_0 = order(&contract);
+        assert_eq!(seeded_0[0], big);
+        assert_eq!(seeded_0[1..], expected_ties(0)[..]);
+
+        contract.set_ordering_seed(U64(1));
+        let seeded_1 = order(&contract);
+        assert_eq!(seeded_1[0], big);
+        assert_eq!(seeded_1[1..], expected_ties(1)[..]);
+        assert_ne!(seeded_0, seeded_1);
+    }
+
+    #[test]
+    fn test_get_weights_above() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+        let asset3 = AccountId::from_str("asset3.near").unwrap();
+
+        let contract = fund_with_weights(
+            &curator,
+            &[(&asset1, 6000), (&asset2, 3000), (&asset3, 1000)],
+        );
+
+        let weights: Vec<AssetId> = contract
+            .get_weights_above(U64(2000))
+            .into_iter()
+            .map(|w| w.asset_address)
+            .collect();
+        assert_eq!(weights, vec![asset1, asset2]);
+
+        // the threshold itself is included
+        assert_eq!(contract.get_weights_above(U64(1000)).len(), 3);
+    }
+
+    #[test]
+    fn test_get_weight_extremes() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+        let asset3 = AccountId::from_str("asset3.near").unwrap();
+        let asset4 = AccountId::from_str("asset4.near").unwrap();
+
+        let contract = IndexFund::with_defaults();
+        let (highest, lowest) = contract.get_weight_extremes();
+        assert!(highest.is_none());
+        assert!(lowest.is_none());
+
+        let contract = fund_with_weights(
+            &curator,
+            &[
+                (&asset1, 2000),
+                (&asset2, 7000),
+                (&asset3, 1000),
+                (&asset4, 0),
+            ],
+        );
+        let (highest, lowest) = contract.get_weight_extremes();
+        let highest = highest.expect("No highest weight");
+        let lowest = lowest.expect("No lowest weight");
+        assert_eq!(highest.asset_address, asset2);
+        assert_eq!(highest.weight, U64(7000));
+        assert_eq!(lowest.asset_address, asset3);
+        assert_eq!(lowest.weight, U64(1000));
+    }
+
+    #[test]
+    fn test_estimate_rebalance() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+        let asset3 = AccountId::from_str("asset3.near").unwrap();
+
+        let mut contract = fund_with_weights(
+            &curator,
+            &[(&asset1, 5000), (&asset2, 2500), (&asset3, 2500)],
+        );
+        set_prices(&mut contract, &[(&asset1, 1), (&asset2, 1), (&asset3, 1)]);
+        set_balances(
+            &mut contract,
+            &[(&asset1, 300), (&asset2, 100), (&asset3, 200)],
+        );
+
+        // NAV 600: asset1 sells 0, asset2 buys 50, asset3 sells 50
+        let estimate = contract.estimate_rebalance();
+        assert_eq!(
+            estimate,
+            RebalanceEstimate {
+                num_trades: 2,
+                total_notional: U128(100),
+                nav: U128(600),
+            }
+        );
+    }
+
+    #[test]
+    fn test_diff_weights() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+        let asset3 = AccountId::from_str("asset3.near").unwrap();
+        let asset4 = AccountId::from_str("asset4.near").unwrap();
+
+        let contract = fund_with_weights(
+            &curator,
+            &[(&asset1, 5000), (&asset2, 3000), (&asset3, 2000)],
+        );
+        let diff = contract.diff_weights(vec![
+            AssetWeight {
+                weight: U64(6000),
+                asset_address: asset1.clone(),
+            },
+            AssetWeight {
+                weight: U64(0),
+                asset_address: asset2.clone(),
+            },
+            AssetWeight {
+                weight: U64(2000),
+                asset_address: asset3.clone(),
+            },
+            AssetWeight {
+                weight: U64(2000),
+                asset_address: asset4.clone(),
+            },
+        ]);
+
+        // asset3 is unchanged and left out
+        assert_eq!(
+            diff,
+            vec![
+                WeightDiff {
+                    asset_address: asset1.clone(),
+                    old: U64(5000),
+                    new: U64(6000),
+                    delta: 1000,
+                },
+                WeightDiff {
+                    asset_address: asset2,
+                    old: U64(3000),
+                    new: U64(0),
+                    delta: -3000,
+                },
+                WeightDiff {
+                    asset_address: asset4.clone(),
+                    old: U64(0),
+                    new: U64(2000),
+                    delta: 2000,
+                },
+            ]
+        );
+        assert_eq!(contract.assets.get(&asset1).unwrap().weight, U64(5000));
+        assert!(!contract.is_asset(asset4));
+    }
+
+    #[test]
+    fn test_simulate_rebalance_with_prices() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset1, 5000), (&asset2, 5000)]);
+        set_prices(&mut contract, &[(&asset1, 10), (&asset2, 10)]);
+        set_balances(&mut contract, &[(&asset1, 100), (&asset2, 100)]);
+        assert_eq!(contract.estimate_rebalance().num_trades, 0);
+
+        // asset1 drops 30%: NAV 1700, each target 850
+        let trades = contract.simulate_rebalance_with_prices(vec![(asset1.clone(), U128(7))]);
+        assert_eq!(trades.len(), 2);
+        assert!(trades.contains(&(asset1.clone(), 150)));
+        assert!(trades.contains(&(asset2.clone(), -150)));
+
+        assert_eq!(contract.assets.get(&asset1).unwrap().last_price, U128(10));
+        assert_eq!(contract.get_nav(), U128(2000));
+    }
+
+    #[test]
+    fn test_get_value_weights() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+        let asset3 = AccountId::from_str("asset3.near").unwrap();
+
+        let mut contract = fund_with_weights(
+            &curator,
+            &[(&asset1, 3400), (&asset2, 3300), (&asset3, 3300)],
+        );
+        assert!(contract.get_value_weights().iter().all(|(_, w)| w.0 == 0));
+
+        // drifted to 1:1:1 in value, which doesn't split evenly into basis points
+        set_prices(&mut contract, &[(&asset1, 1), (&asset2, 2), (&asset3, 1)]);
+        set_balances(
+            &mut contract,
+            &[(&asset1, 100), (&asset2, 50), (&asset3, 100)],
+        );
+        let value_weights = contract.get_value_weights();
+        assert_eq!(value_weights.iter().map(|(_, w)| w.0).sum::<u64>(), 10000);
+        assert!(value_weights.contains(&(asset1.clone(), U64(3334))));
+        assert!(value_weights.contains(&(asset2.clone(), U64(3333))));
+        assert!(value_weights.contains(&(asset3.clone(), U64(3333))));
+        // targets are untouched
+        assert_eq!(contract.assets.get(&asset1).unwrap().weight, U64(3400));
+    }
+
+    #[test]
+    fn test_get_bound_breaches() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+        let asset3 = AccountId::from_str("asset3.near").unwrap();
+
+        let mut contract = fund_with_weights(
+            &curator,
+            &[(&asset1, 4000), (&asset2, 4000), (&asset3, 2000)],
+        );
+        contract.set_asset_weight_bounds(asset1.clone(), U64(3000), U64(5000));
+        contract.set_asset_weight_bounds(asset3.clone(), U64(1000), U64(3000));
+        set_prices(&mut contract, &[(&asset1, 1), (&asset2, 1), (&asset3, 1)]);
+
+        set_balances(
+            &mut contract,
+            &[(&asset1, 400), (&asset2, 400), (&asset3, 200)],
+        );
+        assert!(contract.get_bound_breaches().is_empty());
+
+        // asset1 rallies to 60% of the fund, pushing asset3 under 10%
+        set_balances(&mut contract, &[(&asset1, 1500)]);
+        let breaches = contract.get_bound_breaches();
+        assert_eq!(breaches.len(), 2);
+        assert!(breaches.contains(&(asset1, BreachKind::AboveMax)));
+        assert!(breaches.contains(&(asset3, BreachKind::BelowMin)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Weight out of bounds for asset1.near")]
+    fn test_update_weights_outside_bounds() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset1, 5000), (&asset2, 5000)]);
+        contract.set_asset_weight_bounds(asset1.clone(), U64(0), U64(6000));
+        contract.update_weights(vec![
+            AssetWeight {
+                weight: U64(7000),
+                asset_address: asset1,
+            },
+            AssetWeight {
+                weight: U64(3000),
+                asset_address: asset2,
+            },
+        ]);
+    }
+
+    #[test]
+    fn test_get_rebalance_readiness() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset1, 5000), (&asset2, 5000)]);
+        set_prices(&mut contract, &[(&asset1, 1), (&asset2, 1)]);
+        set_balances(&mut contract, &[(&asset1, 620), (&asset2, 380)]);
+        contract.set_drift_tolerance_bps(U64(500));
+        contract.rebalance_interval = U64(100);
+        contract.last_rebalance = U64(50);
+
+        let mut context = get_context(curator);
+        context.block_height(120);
+        testing_env!(context.build());
+        assert_eq!(
+            contract.get_rebalance_readiness(),
+            RebalanceReadiness {
+                interval_elapsed: false,
+                blocks_remaining: U64(30),
+                max_drift_bps: U64(1200),
+                tolerance_bps: U64(500),
+                ready: false,
+            }
+        );
+    }
+
+    #[test]
+    fn test_get_portfolio_stats() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+        let asset3 = AccountId::from_str("asset3.near").unwrap();
+
+        let mut contract =
+            fund_with_weights(&curator, &[(&asset1, 6000), (&asset2, 4000), (&asset3, 0)]);
+        assert_eq!(
+            contract.get_portfolio_stats().nav_per_share,
+            U128(NAV_PER_SHARE_SCALE)
+        );
+
+        set_prices(&mut contract, &[(&asset1, 2), (&asset2, 1)]);
+        set_balances(&mut contract, &[(&asset1, 100), (&asset2, 200)]);
+        contract.total_shares = U128(800);
+
+        assert_eq!(
+            contract.get_portfolio_stats(),
+            PortfolioStats {
+                asset_count: 3,
+                active_asset_count: 2,
+                nav: U128(400),
+                total_shares: U128(800),
+                nav_per_share: U128(NAV_PER_SHARE_SCALE / 2),
+                total_weight: U64(10000),
+            }
+        );
+    }
+
+    #[test]
+    fn test_set_weights_from_balances_places_dust_deterministically() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        // inserted out of order so map order and id order disagree
+        let assets: Vec<AccountId> = ["g", "c", "e", "a", "f", "b", "d"]
+            .iter()
+            .map(|name| AccountId::from_str(&format!("{}.near", name)).unwrap())
+            .collect();
+        let weights: Vec<(&AccountId, u64)> = assets
+            .iter()
+            .zip([1000, 1000, 1000, 1000, 2000, 2000, 2000])
+            .collect();
+
+        let mut contract = fund_with_weights(&curator, &weights);
+        let equal: Vec<(&AccountId, u128)> = assets.iter().map(|a| (a, 1)).collect();
+        set_prices(&mut contract, &equal);
+        let balances: Vec<(&AccountId, u128)> = assets.iter().map(|a| (a, 100)).collect();
+        set_balances(&mut contract, &balances);
+
+        // 10000 / 7 leaves 4 bps of dust, which all goes to the smallest id among the tied assets
+        contract.set_weights_from_balances();
+        for asset_id in assets.iter() {
+            let expected = if asset_id.as_str() == "a.near" {
+                1432
+            } else {
+                1428
+            };
+            assert_eq!(contract.assets.get(asset_id).unwrap().weight, U64(expected));
+        }
+    }
+
+    fn zero_funded_asset(strict: bool) -> IndexFund {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset1, 5000), (&asset2, 5000)]);
+        set_balances(&mut contract, &[(&asset2, 100)]);
+        contract.set_strict_weight_zeroing(strict);
+        contract.update_weights(vec![
+            AssetWeight {
+                weight: U64(10000),
+                asset_address: asset1,
+            },
+            AssetWeight {
+                weight: U64(0),
+                asset_address: asset2,
+            },
+        ]);
+        contract
+    }
+
+    #[test]
+    fn test_zero_funded_asset_lenient() {
+        let contract = zero_funded_asset(false);
+        assert!(get_logs().contains(
+            &"Zeroing the weight of asset2.near which still holds a balance".to_string()
+        ));
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+        assert_eq!(contract.assets.get(&asset2).unwrap().weight, U64(0));
+    }
+
+    #[test]
+    #[should_panic(expected = "Zeroing the weight of asset2.near which still holds a balance")]
+    fn test_zero_funded_asset_strict() {
+        zero_funded_asset(true);
+    }
+
+    fn update_with_tolerance(w1: u64, w2: u64, w3: u64) -> IndexFund {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+        let asset3 = AccountId::from_str("asset3.near").unwrap();
+
+        let mut contract = fund_with_weights(
+            &curator,
+            &[(&asset1, 5000), (&asset2, 3000), (&asset3, 2000)],
+        );
+        contract.set_weight_sum_tolerance_bps(U64(10));
+        contract.update_weights(
+            [(asset1, w1), (asset2, w2), (asset3, w3)]
+                .into_iter()
+                .map(|(asset_address, weight)| AssetWeight {
+                    weight: U64(weight),
+                    asset_address,
+                })
+                .collect(),
+        );
+        contract
+    }
+
+    #[test]
+    fn test_update_weights_within_tolerance() {
+        // 3333 * 3 = 9999 is scaled back up, the extra bps going to the smallest id on a tie
+        let contract = update_with_tolerance(3333, 3333, 3333);
+        let weights: Vec<u64> = ["asset1.near", "asset2.near", "asset3.near"]
+            .iter()
+            .map(|id| {
+                let asset_id = AccountId::from_str(id).unwrap();
+                contract.assets.get(&asset_id).unwrap().weight.0
+            })
+            .collect();
+        assert_eq!(weights, vec![3334, 3333, 3333]);
+        assert!(get_logs().contains(&"Renormalized weights summing to 9999 bps".to_string()));
+    }
+
+    #[test]
+    #[should_panic(expected = "Final weights must sum to 100%")]
+    fn test_update_weights_outside_tolerance() {
+        update_with_tolerance(3330, 3330, 3329);
+    }
+
+    fn zero_out_asset2(auto_prune: bool) -> IndexFund {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset1, 5000), (&asset2, 5000)]);
+        contract.set_auto_prune_zero_weight(auto_prune);
+        contract.update_weights(vec![
+            AssetWeight {
+                weight: U64(10000),
+                asset_address: asset1,
+            },
+            AssetWeight {
+                weight: U64(0),
+                asset_address: asset2,
+            },
+        ]);
+        contract
+    }
+
+    #[test]
+    fn test_auto_prune_zero_weight() {
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+        assert!(!zero_out_asset2(true).is_asset(asset2));
+    }
+
+    #[test]
+    fn test_zero_weight_kept_without_auto_prune() {
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+        assert!(zero_out_asset2(false).is_asset(asset2));
+    }
+
+    #[test]
+    fn test_auto_prune_keeps_funded_assets() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset1, 5000), (&asset2, 5000)]);
+        set_balances(&mut contract, &[(&asset2, 1)]);
+        contract.set_auto_prune_zero_weight(true);
+        contract.update_weights(vec![
+            AssetWeight {
+                weight: U64(10000),
+                asset_address: asset1,
+            },
+            AssetWeight {
+                weight: U64(0),
+                asset_address: asset2.clone(),
+            },
+        ]);
+        assert!(contract.is_asset(asset2));
+    }
+
+    #[test]
+    fn test_update_weights_logs_dust_weights() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+
+        fund_with_weights(&curator, &[(&asset1, 9999), (&asset2, 1)]);
+        assert!(get_logs().contains(&"Suspicious dust weights for asset2.near".to_string()));
+    }
+
+    #[test]
+    fn test_delegate_update_weights() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let delegate = AccountId::from_str("delegate.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+        let weights = |w1: u64, w2: u64| {
+            vec![
+                AssetWeight {
+                    weight: U64(w1),
+                    asset_address: asset1.clone(),
+                },
+                AssetWeight {
+                    weight: U64(w2),
+                    asset_address: asset2.clone(),
+                },
+            ]
+        };
+
+        let mut contract = fund_with_weights(&curator, &[(&asset1, 5000), (&asset2, 5000)]);
+        contract.set_delegate(Some(delegate.clone()), U64(500));
+
+        testing_env!(get_context(delegate.clone()).build());
+        contract.update_weights(weights(5500, 4500));
+        assert_eq!(contract.assets.get(&asset1).unwrap().weight, U64(5500));
+
+        // the curator isn't limited
+        testing_env!(get_context(curator).build());
+        contract.update_weights(weights(9000, 1000));
+        assert_eq!(contract.assets.get(&asset1).unwrap().weight, U64(9000));
+    }
+
+    #[test]
+    #[should_panic(expected = "Weight change too large for asset1.near")]
+    fn test_delegate_update_weights_over_limit() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let delegate = AccountId::from_str("delegate.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset1, 5000), (&asset2, 5000)]);
+        contract.set_delegate(Some(delegate.clone()), U64(500));
+
+        testing_env!(get_context(delegate).build());
+        contract.update_weights(vec![
+            AssetWeight {
+                weight: U64(7000),
+                asset_address: asset1,
+            },
+            AssetWeight {
+                weight: U64(3000),
+                asset_address: asset2,
+            },
+        ]);
+    }
+
+    #[test]
+    fn test_emergency_set_weights() {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset1, 5000), (&asset2, 5000)]);
+        contract.owner_id = Some(owner.clone());
+        testing_env!(get_context(owner).build());
+
+        contract.emergency_set_weights(vec![
+            AssetWeight {
+                weight: U64(0),
+                asset_address: asset1.clone(),
+            },
+            AssetWeight {
+                weight: U64(10000),
+                asset_address: asset2.clone(),
+            },
+        ]);
+
+        assert!(contract.paused);
+        assert_eq!(contract.assets.get(&asset1).unwrap().weight, U64(0));
+        assert_eq!(contract.assets.get(&asset2).unwrap().weight, U64(10000));
+        let logs = get_logs();
+        assert!(logs
+            .iter()
+            .any(|log| log.contains(r#""event":"emergency_weights_set""#)));
+        assert!(logs.iter().any(|log| log.contains(r#""event":"paused""#)));
+
+        // still works as a second override while paused
+        contract.emergency_set_weights(vec![
+            AssetWeight {
+                weight: U64(10000),
+                asset_address: asset1.clone(),
+            },
+            AssetWeight {
+                weight: U64(0),
+                asset_address: asset2,
+            },
+        ]);
+        assert_eq!(contract.assets.get(&asset1).unwrap().weight, U64(10000));
+    }
+
+    #[test]
+    #[should_panic(expected = "Final weights must sum to 100%")]
+    fn test_emergency_set_weights_invalid_sum() {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+        testing_env!(get_context(owner.clone()).build());
+
+        let mut contract = IndexFund::with_defaults();
+        contract.owner_id = Some(owner);
+        contract.emergency_set_weights(vec![AssetWeight {
+            weight: U64(9000),
+            asset_address: asset,
+        }]);
+    }
+
+    #[test]
+    fn test_migrate_asset() {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        let old = AccountId::from_str("token-v1.near").unwrap();
+        let new = AccountId::from_str("token-v2.near").unwrap();
+        let other = AccountId::from_str("other.near").unwrap();
+
+        let mut contract = fund_with_weights(&owner, &[(&old, 7000), (&other, 3000)]);
+        contract.owner_id = Some(owner);
+        set_prices(&mut contract, &[(&old, 3)]);
+        set_balances(&mut contract, &[(&old, 500)]);
+        contract.set_asset_label(old.clone(), Some("L1".to_string()));
+        let nav = contract.get_nav();
+
+        contract.migrate_asset(old.clone(), new.clone());
+        assert!(!contract.is_asset(old));
+        let holding = contract.assets.get(&new).unwrap();
+        assert_eq!(holding.balance, U128(500));
+        assert_eq!(holding.weight, U64(7000));
+        assert_eq!(holding.last_price, U128(3));
+        assert_eq!(holding.label, Some("L1".to_string()));
+        assert_eq!(contract.get_nav(), nav);
+    }
+
+    #[test]
+    fn test_migrate_asset_remaps_redemption_queue() {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        let investor = AccountId::from_str("investor.near").unwrap();
+        let old = AccountId::from_str("token-v1.near").unwrap();
+        let new = AccountId::from_str("token-v2.near").unwrap();
+        let other = AccountId::from_str("other.near").unwrap();
+
+        let mut contract = fund_with_weights(&owner, &[(&old, 7000), (&other, 3000)]);
+        contract.owner_id = Some(owner);
+        contract.redemption_queue.push(&RedemptionRequest {
+            account: investor.clone(),
+            amounts: vec![(old.clone(), U128(70)), (other.clone(), U128(30))],
+            requested_at: U64(0),
+        });
+
+        contract.migrate_asset(old, new.clone());
+        let queue = contract.get_redemption_queue(0, 10);
+        assert_eq!(
+            queue[0].amounts,
+            vec![(new.clone(), U128(70)), (other, U128(30))]
+        );
+
+        // the payout goes to the new token contract
+        contract.process_redemptions(1);
+        assert!(created_calls().contains(&(new, "ft_transfer".to_string())));
+    }
+
+    #[test]
+    #[should_panic(expected = "New asset id already exists")]
+    fn test_migrate_asset_onto_existing() {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+
+        let mut contract = fund_with_weights(&owner, &[(&asset1, 5000), (&asset2, 5000)]);
+        contract.owner_id = Some(owner);
+        contract.migrate_asset(asset1, asset2);
+    }
+
+    #[test]
+    fn test_remove_asset() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset1, 10000), (&asset2, 0)]);
+        contract.remove_asset(asset2.clone());
+        assert!(!contract.is_asset(asset2));
+
+        // with no shares left, even the last asset can go
+        contract.remove_asset(asset1.clone());
+        assert!(contract.get_assets().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Can't remove the last asset while shares are outstanding")]
+    fn test_remove_last_asset_with_shares() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset, 10000)]);
+        contract.total_shares = U128(100);
+        contract.remove_asset(asset);
+    }
+
+    #[test]
+    #[should_panic(expected = "Asset still has weight")]
+    fn test_remove_weighted_asset() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset1, 5000), (&asset2, 5000)]);
+        contract.remove_asset(asset2);
+    }
+
+    #[test]
+    fn test_get_curator_and_oracle() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let oracle = AccountId::from_str("oracle.near").unwrap();
+
+        testing_env!(get_context(curator.clone()).build());
+
+        let mut contract = IndexFund::with_defaults();
+        assert_eq!(contract.get_curator(), None);
+        assert_eq!(contract.get_oracle(), None);
+
+        contract.curator_address = Some(curator.clone());
+        contract.set_oracle(oracle.clone());
+        assert_eq!(contract.get_curator(), Some(curator));
+        assert_eq!(contract.get_oracle(), Some(oracle));
+    }
+
+    fn expect_value(result: PromiseOrValue<U128>) -> U128 {
+        match result {
+            PromiseOrValue::Value(value) => value,
+            PromiseOrValue::Promise(_) => panic!("Expected a value"),
+        }
+    }
+
+    #[test]
+    fn test_ft_on_transfer_mints_shares() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let investor = AccountId::from_str("investor.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset1, 5000), (&asset2, 5000)]);
+        set_prices(&mut contract, &[(&asset1, 2), (&asset2, 5)]);
+        contract.shares.insert(&investor, &U128(0));
+
+        testing_env!(get_context(asset1.clone()).build());
+        let unused = contract.ft_on_transfer(investor.clone(), U128(100), String::new());
+        assert_eq!(expect_value(unused), U128(0));
+        assert_eq!(contract.shares.get(&investor), Some(U128(200)));
+        assert_eq!(contract.get_nav(), U128(200));
+
+        // a later deposit mints pro rata to the value it adds
+        testing_env!(get_context(asset2.clone()).build());
+        contract.ft_on_transfer(investor.clone(), U128(20), String::new());
+        assert_eq!(contract.shares.get(&investor), Some(U128(300)));
+        assert_eq!(contract.total_shares, U128(300));
+        assert_eq!(contract.assets.get(&asset2).unwrap().balance, U128(20));
+    }
+
+    #[test]
+    #[should_panic(expected = "Reentrant ft_on_transfer")]
+    fn test_ft_on_transfer_rejects_reentrancy() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let investor = AccountId::from_str("investor.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset, 10000)]);
+        set_prices(&mut contract, &[(&asset, 1)]);
+        contract.shares.insert(&investor, &U128(0));
+
+        testing_env!(get_context(asset.clone()).build());
+        contract.ft_on_transfer(investor.clone(), U128(100), String::new());
+        assert!(!contract.processing_transfer);
+
+        // simulate a token calling back in while a transfer is still being processed
+        contract.processing_transfer = true;
+        contract.ft_on_transfer(investor, U128(100), String::new());
+    }
+
+    #[test]
+    fn test_ft_on_transfer_entry_fee() {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let investor = AccountId::from_str("investor.near").unwrap();
+        let treasury = AccountId::from_str("treasury.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset, 10000)]);
+        set_prices(&mut contract, &[(&asset, 1)]);
+        contract.shares.insert(&investor, &U128(0));
+
+        contract.owner_id = Some(owner.clone());
+        testing_env!(get_context(owner).build());
+        contract.set_fee_recipient(treasury.clone());
+        contract.set_entry_fee_bps(U64(100));
+
+        testing_env!(get_context(asset.clone()).build());
+        contract.ft_on_transfer(investor.clone(), U128(10000), String::new());
+
+        assert_eq!(contract.shares.get(&investor), Some(U128(9900)));
+        assert_eq!(contract.shares.get(&treasury), Some(U128(100)));
+        assert_eq!(contract.total_shares, U128(10000));
+    }
+
+    #[test]
+    fn test_redeem() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let investor = AccountId::from_str("investor.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset1, 5000), (&asset2, 5000)]);
+        set_prices(&mut contract, &[(&asset1, 1), (&asset2, 1)]);
+        set_balances(&mut contract, &[(&asset1, 500), (&asset2, 500)]);
+        contract.shares.insert(&investor, &U128(100));
+        contract.total_shares = U128(1000);
+
+        let mut context = get_context(investor.clone());
+        context.attached_deposit(NearToken::from_yoctonear(1));
+        testing_env!(context.build());
+
+        let payouts = contract.redeem(U128(100));
+        assert_eq!(payouts.len(), 2);
+        assert!(payouts.contains(&(asset1.clone(), U128(50))));
+        assert!(payouts.contains(&(asset2.clone(), U128(50))));
+        assert_eq!(contract.shares.get(&investor), Some(U128(0)));
+        assert_eq!(contract.total_shares, U128(900));
+        assert_eq!(contract.assets.get(&asset1).unwrap().balance, U128(450));
+
+        // a failed payout stays owed to the investor rather than going back to the fund
+        let calls = created_calls();
+        assert!(calls.contains(&(asset1, "ft_transfer".to_string())));
+        let current = env::current_account_id();
+        assert_eq!(
+            calls
+                .iter()
+                .filter(
+                    |(receiver, method)| *receiver == current && method == "on_redemption_transfer"
+                )
+                .count(),
+            2
+        );
+        assert!(!calls
+            .iter()
+            .any(|(_, method)| method == "on_asset_transfer"));
+    }
+
+    #[test]
+    fn test_mul_div() {
+        assert_eq!(IndexFund::mul_div(6, 7, 4), 10);
+        assert_eq!(IndexFund::mul_div(u128::MAX, 3, 3), u128::MAX);
+        assert_eq!(
+            IndexFund::mul_div(u128::MAX, u128::MAX, u128::MAX),
+            u128::MAX
+        );
+        assert_eq!(IndexFund::mul_div(1 << 100, 1 << 100, 1 << 90), 1 << 110);
+        // exact even though the product needs all 256 bits
+        assert_eq!(
+            IndexFund::mul_div(u128::MAX, u128::MAX - 1, u128::MAX),
+            u128::MAX - 1
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Arithmetic overflow")]
+    fn test_mul_div_overflow() {
+        IndexFund::mul_div(u128::MAX, 2, 1);
+    }
+
+    #[test]
+    fn test_24_decimal_magnitudes() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let alice = AccountId::from_str("alice.near").unwrap();
+        let bob = AccountId::from_str("bob.near").unwrap();
+        let wnear = AccountId::from_str("wrap.near").unwrap();
+        let near = 10u128.pow(24);
+
+        let mut contract = fund_with_weights(&curator, &[(&wnear, 10000)]);
+        let mut holding = contract.assets.get(&wnear).unwrap();
+        holding.decimals = 24;
+        // $5 in an 18-decimal quote asset
+        holding.last_price = U128(5 * 10u128.pow(18));
+        contract.assets.insert(&wnear, &holding);
+        contract.shares.insert(&alice, &U128(0));
+        contract.shares.insert(&bob, &U128(0));
+
+        let alice_shares = contract.internal_deposit(&alice, &wnear, 1_000_000 * near);
+        let bob_shares = contract.internal_deposit(&bob, &wnear, 2_000_000 * near);
+        assert_eq!(alice_shares, 5 * 10u128.pow(24));
+        assert_eq!(bob_shares, 2 * alice_shares);
+        assert_eq!(contract.get_nav(), U128(15 * 10u128.pow(24)));
+        assert_eq!(contract.get_nav_per_share(), U128(NAV_PER_SHARE_SCALE));
+
+        let payouts = contract.internal_redeem(&bob, bob_shares);
+        assert_eq!(payouts, vec![(wnear, U128(2_000_000 * near))]);
+    }
+
+    fn fund_for_can_redeem(investor: &AccountId) -> IndexFund {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+
+        let mut contract = fund_with_weights(&owner, &[(&asset, 10000)]);
+        set_balances(&mut contract, &[(&asset, 1000)]);
+        contract.shares.insert(investor, &U128(100));
+        contract.total_shares = U128(1000);
+        contract
+    }
+
+    #[test]
+    fn test_can_redeem() {
+        let investor = AccountId::from_str("investor.near").unwrap();
+        let contract = fund_for_can_redeem(&investor);
+        assert_eq!(contract.can_redeem(investor), (true, None));
+    }
+
+    #[test]
+    fn test_can_redeem_paused() {
+        let investor = AccountId::from_str("investor.near").unwrap();
+        let mut contract = fund_for_can_redeem(&investor);
+        contract.paused = true;
+        assert_eq!(
+            contract.can_redeem(investor),
+            (false, Some("Contract is paused".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_can_redeem_no_shares() {
+        let investor = AccountId::from_str("investor.near").unwrap();
+        let stranger = AccountId::from_str("stranger.near").unwrap();
+        let mut contract = fund_for_can_redeem(&investor);
+        contract.shares.insert(&stranger, &U128(0));
+        assert_eq!(
+            contract.can_redeem(stranger),
+            (false, Some("No shares".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_can_redeem_locked() {
+        let investor = AccountId::from_str("investor.near").unwrap();
+        let mut contract = fund_for_can_redeem(&investor);
+        contract.share_lockup_ns = U64(1000);
+        contract.last_deposit_at.insert(&investor, &U64(50));
+        assert_eq!(
+            contract.can_redeem(investor.clone()),
+            (false, Some("Shares are locked".to_string()))
+        );
+
+        let mut context = get_context(investor.clone());
+        context.block_timestamp(1050);
+        testing_env!(context.build());
+        assert_eq!(contract.can_redeem(investor), (true, None));
+    }
+
+    #[test]
+    fn test_admin_set_holdings() {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+
+        let mut contract = fund_with_weights(&owner, &[(&asset1, 5000), (&asset2, 5000)]);
+        contract.owner_id = Some(owner);
+        contract.paused = true;
+
+        let mut holding1 = contract.assets.get(&asset1).unwrap();
+        holding1.balance = U128(700);
+        holding1.last_price = U128(3);
+        holding1.weight = U64(6000);
+        let mut holding2 = contract.assets.get(&asset2).unwrap();
+        holding2.balance = U128(300);
+        holding2.weight = U64(4000);
+        contract.admin_set_holdings(vec![(asset1.clone(), holding1), (asset2.clone(), holding2)]);
+
+        let holding1 = contract.assets.get(&asset1).unwrap();
+        assert_eq!(holding1.balance, U128(700));
+        assert_eq!(holding1.last_price, U128(3));
+        assert_eq!(holding1.weight, U64(6000));
+        assert_eq!(contract.assets.get(&asset2).unwrap().balance, U128(300));
+
+        let audit: Vec<String> = get_logs()
+            .into_iter()
+            .filter(|log| log.contains("holding_overwritten"))
+            .collect();
+        assert_eq!(
+            audit,
+            vec![
+                r#"EVENT_JSON:{"standard":"index_fund","version":"1.0.0","event":"holding_overwritten","data":{"by":"owner.near","asset_address":"asset1.near","balance":"700","weight":"6000","last_price":"3"}}"#,
+                r#"EVENT_JSON:{"standard":"index_fund","version":"1.0.0","event":"holding_overwritten","data":{"by":"owner.near","asset_address":"asset2.near","balance":"300","weight":"4000","last_price":"0"}}"#,
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Contract is not paused")]
+    fn test_admin_set_holdings_unpaused() {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+
+        let mut contract = fund_with_weights(&owner, &[(&asset, 10000)]);
+        contract.owner_id = Some(owner);
+        let holding = contract.assets.get(&asset).unwrap();
+        contract.admin_set_holdings(vec![(asset, holding)]);
+    }
+
+    #[test]
+    fn test_emergency_withdraw() {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        let safe = AccountId::from_str("safe.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+
+        let mut contract = fund_with_weights(&owner, &[(&asset, 10000)]);
+        contract.owner_id = Some(owner.clone());
+        contract.paused = true;
+        set_balances(&mut contract, &[(&asset, 500)]);
+
+        let mut context = get_context(owner);
+        context.attached_deposit(NearToken::from_yoctonear(1));
+        testing_env!(context.build());
+        contract.emergency_withdraw(asset.clone(), U128(200), safe);
+        assert_eq!(contract.assets.get(&asset).unwrap().balance, U128(300));
+        assert_eq!(
+            get_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"index_fund","version":"1.0.0","event":"emergency_withdraw","data":{"by":"owner.near","asset_address":"asset.near","amount":"200","recipient":"safe.near"}}"#
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Contract is not paused")]
+    fn test_emergency_withdraw_unpaused() {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        let safe = AccountId::from_str("safe.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+
+        let mut contract = fund_with_weights(&owner, &[(&asset, 10000)]);
+        contract.owner_id = Some(owner.clone());
+        set_balances(&mut contract, &[(&asset, 500)]);
+
+        let mut context = get_context(owner);
+        context.attached_deposit(NearToken::from_yoctonear(1));
+        testing_env!(context.build());
+        contract.emergency_withdraw(asset, U128(200), safe);
+    }
+
+    #[test]
+    fn test_ft_transfer_respects_lockup() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let investor = AccountId::from_str("investor.near").unwrap();
+        let friend = AccountId::from_str("friend.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset, 10000)]);
+        contract.owner_id = Some(curator.clone());
+        contract.set_share_lockup_ns(U64(1000));
+        set_prices(&mut contract, &[(&asset, 1)]);
+        contract.shares.insert(&investor, &U128(0));
+        contract.shares.insert(&friend, &U128(0));
+        contract.internal_deposit(&investor, &asset, 100);
+
+        let mut context = get_context(investor.clone());
+        context.attached_deposit(NearToken::from_yoctonear(1));
+        context.block_timestamp(1100);
+        testing_env!(context.build());
+        contract.ft_transfer(friend.clone(), U128(40), None);
+        assert_eq!(contract.ft_balance_of(investor), U128(60));
+        assert_eq!(contract.ft_balance_of(friend), U128(40));
+        assert_eq!(contract.ft_total_supply(), U128(100));
+    }
+
+    #[test]
+    #[should_panic(expected = "Shares are locked")]
+    fn test_ft_transfer_within_lockup() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let investor = AccountId::from_str("investor.near").unwrap();
+        let friend = AccountId::from_str("friend.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset, 10000)]);
+        contract.owner_id = Some(curator.clone());
+        contract.set_share_lockup_ns(U64(1000));
+        set_prices(&mut contract, &[(&asset, 1)]);
+        contract.shares.insert(&investor, &U128(0));
+        contract.shares.insert(&friend, &U128(0));
+        contract.internal_deposit(&investor, &asset, 100);
+
+        let mut context = get_context(investor);
+        context.attached_deposit(NearToken::from_yoctonear(1));
+        context.block_timestamp(1099);
+        testing_env!(context.build());
+        contract.ft_transfer(friend, U128(40), None);
+    }
+
+    // 1 of 3 shares of 10 units of a 100-priced asset and 1000 of a 1-priced one
+    fn redeem_uneven(dust_policy: DustPolicy) -> (Vec<(AccountId, U128)>, IndexFund) {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let investor = AccountId::from_str("investor.near").unwrap();
+        let btc = AccountId::from_str("btc.near").unwrap();
+        let usd = AccountId::from_str("usd.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&btc, 5000), (&usd, 5000)]);
+        contract.dust_policy = dust_policy;
+        set_prices(&mut contract, &[(&btc, 100), (&usd, 1)]);
+        set_balances(&mut contract, &[(&btc, 10), (&usd, 1000)]);
+        contract.shares.insert(&investor, &U128(1));
+        contract.total_shares = U128(3);
+
+        let mut context = get_context(investor);
+        context.attached_deposit(NearToken::from_yoctonear(1));
+        testing_env!(context.build());
+        (contract.redeem(U128(1)), contract)
+    }
+
+    #[test]
+    fn test_redeem_dust_left_in_fund() {
+        let btc = AccountId::from_str("btc.near").unwrap();
+        let usd = AccountId::from_str("usd.near").unwrap();
+        let (payouts, contract) = redeem_uneven(DustPolicy::LeaveInFund);
+
+        // 300 + 333 paid of the 666 the share is worth
+        assert!(payouts.contains(&(btc, U128(3))));
+        assert!(payouts.contains(&(usd, U128(333))));
+        assert_eq!(contract.get_nav(), U128(1367));
+    }
+
+    #[test]
+    fn test_redeem_dust_sent_with_largest() {
+        let btc = AccountId::from_str("btc.near").unwrap();
+        let usd = AccountId::from_str("usd.near").unwrap();
+        let (payouts, contract) = redeem_uneven(DustPolicy::SendWithLargest);
+
+        // the 33 short is paid out in usd, the largest payout
+        assert!(payouts.contains(&(btc, U128(3))));
+        assert!(payouts.contains(&(usd, U128(366))));
+        assert_eq!(contract.get_nav(), U128(1334));
+    }
+
+    // 1000 deposited into a fund whose shares are all gone but still holds 5 of dust
+    fn fund_after_dust_deposit(policy: ZeroSupplyPolicy) -> IndexFund {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        let treasury = AccountId::from_str("treasury.near").unwrap();
+        let investor = AccountId::from_str("investor.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+
+        let mut contract = fund_with_weights(&owner, &[(&asset, 10000)]);
+        contract.owner_id = Some(owner);
+        contract.set_fee_recipient(treasury);
+        contract.set_zero_supply_policy(policy);
+        set_prices(&mut contract, &[(&asset, 1)]);
+        set_balances(&mut contract, &[(&asset, 5)]);
+        assert_eq!(contract.total_shares, U128(0));
+
+        contract.shares.insert(&investor, &U128(0));
+        contract.internal_deposit(&investor, &asset, 1000);
+        contract
+    }
+
+    #[test]
+    fn test_zero_supply_clean_mint() {
+        let investor = AccountId::from_str("investor.near").unwrap();
+        let contract = fund_after_dust_deposit(ZeroSupplyPolicy::CleanMint);
+
+        // the investor's 1000 shares are worth 1005
+        assert_eq!(contract.shares.get(&investor), Some(U128(1000)));
+        assert_eq!(contract.total_shares, U128(1000));
+        assert_eq!(contract.get_nav(), U128(1005));
+    }
+
+    #[test]
+    fn test_zero_supply_sweep_to_fee_recipient() {
+        let treasury = AccountId::from_str("treasury.near").unwrap();
+        let investor = AccountId::from_str("investor.near").unwrap();
+        let contract = fund_after_dust_deposit(ZeroSupplyPolicy::SweepToFeeRecipient);
+
+        assert_eq!(contract.shares.get(&treasury), Some(U128(5)));
+        assert_eq!(contract.shares.get(&investor), Some(U128(1000)));
+        assert_eq!(contract.total_shares, U128(1005));
+        assert_eq!(contract.get_nav_per_share(), U128(NAV_PER_SHARE_SCALE));
+        assert_eq!(contract.get_total_fees_collected(), U128(5));
+    }
+
+    #[test]
+    fn test_wind_down() {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        let alice = AccountId::from_str("alice.near").unwrap();
+        let bob = AccountId::from_str("bob.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+
+        let mut contract = fund_with_weights(&owner, &[(&asset, 10000)]);
+        contract.owner_id = Some(owner.clone());
+        set_prices(&mut contract, &[(&asset, 1)]);
+        set_balances(&mut contract, &[(&asset, 1000)]);
+        contract.shares.insert(&alice, &U128(400));
+        contract.shares.insert(&bob, &U128(600));
+        contract.total_shares = U128(1000);
+        // bob's shares would still be locked
+        contract.share_lockup_ns = U64(1_000_000);
+        contract.last_deposit_at.insert(&bob, &U64(100));
+        contract.initiate_wind_down();
+
+        testing_env!(get_context(asset.clone()).build());
+        let unused = contract.ft_on_transfer(alice.clone(), U128(100), String::new());
+        assert_eq!(expect_value(unused), U128(100));
+        assert_eq!(contract.total_shares, U128(1000));
+
+        for (holder, shares) in [(&alice, 400), (&bob, 600)] {
+            let mut context = get_context(holder.clone());
+            context.attached_deposit(NearToken::from_yoctonear(1));
+            testing_env!(context.build());
+            contract.redeem(U128(shares));
+        }
+        assert_eq!(contract.total_shares, U128(0));
+        assert_eq!(contract.assets.get(&asset).unwrap().balance, U128(0));
+
+        testing_env!(get_context(owner).build());
+        contract.close_fund();
+        assert!(contract.paused);
+    }
+
+    #[test]
+    #[should_panic(expected = "Shares are still outstanding")]
+    fn test_close_fund_with_shares() {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+
+        let mut contract = fund_with_weights(&owner, &[(&asset, 10000)]);
+        contract.owner_id = Some(owner);
+        contract.total_shares = U128(1);
+        contract.initiate_wind_down();
+        contract.close_fund();
+    }
+
+    #[test]
+    fn test_total_fees_collected() {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        let treasury = AccountId::from_str("treasury.near").unwrap();
+        let investor = AccountId::from_str("investor.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+
+        let mut contract = fund_with_weights(&owner, &[(&asset, 10000)]);
+        contract.owner_id = Some(owner.clone());
+        set_prices(&mut contract, &[(&asset, 1)]);
+        contract.shares.insert(&investor, &U128(0));
+        contract.set_fee_recipient(treasury);
+        contract.set_entry_fee_bps(U64(100));
+        contract.set_exit_fee_bps(U64(200));
+        contract.set_management_fee_bps(U64(1000));
+
+        // entry: 1% of a 10000 deposit
+        testing_env!(get_context(asset.clone()).build());
+        contract.ft_on_transfer(investor.clone(), U128(10000), String::new());
+        assert_eq!(contract.get_total_fees_collected(), U128(100));
+
+        // exit: 2% of the 1000 tokens 1000 shares redeem for
+        let mut context = get_context(investor.clone());
+        context.attached_deposit(NearToken::from_yoctonear(1));
+        testing_env!(context.build());
+        contract.redeem(U128(1000));
+        assert_eq!(contract.get_total_fees_collected(), U128(120));
+
+        // management: a year at 10% mints 900 shares, 900 of the 9900 then backing the 9020 NAV
+        let mut context = get_context(owner);
+        context.block_timestamp(100 + NS_PER_YEAR);
+        testing_env!(context.build());
+        assert_eq!(contract.accrue_management_fee(), U128(900));
+        assert_eq!(
+            contract.get_total_fees_collected(),
+            U128(120 + 9020 * 900 / 9900)
+        );
+    }
+
+    #[test]
+    fn test_redeem_exit_fee() {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let investor = AccountId::from_str("investor.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset, 10000)]);
+        set_prices(&mut contract, &[(&asset, 1)]);
+        set_balances(&mut contract, &[(&asset, 1000)]);
+        contract.shares.insert(&investor, &U128(100));
+        contract.total_shares = U128(1000);
+
+        contract.owner_id = Some(owner.clone());
+        testing_env!(get_context(owner).build());
+        contract.set_exit_fee_bps(U64(100));
+
+        let mut context = get_context(investor.clone());
+        context.attached_deposit(NearToken::from_yoctonear(1));
+        testing_env!(context.build());
+
+        // 1% of the 100 token payout stays in the fund
+        let payouts = contract.redeem(U128(100));
+        assert_eq!(payouts, vec![(asset.clone(), U128(99))]);
+        assert_eq!(contract.total_shares, U128(900));
+        assert_eq!(contract.get_nav(), U128(901));
+
+        // remaining holders now own more per share than before
+        assert!(contract.get_nav().0 * 1000 / contract.total_shares.0 > 1000);
+    }
+
+    #[test]
+    fn test_get_pending_redemption_value() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let alice = AccountId::from_str("alice.near").unwrap();
+        let bob = AccountId::from_str("bob.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset1, 5000), (&asset2, 5000)]);
+        set_prices(&mut contract, &[(&asset1, 2), (&asset2, 5)]);
+        set_balances(&mut contract, &[(&asset1, 1000), (&asset2, 400)]);
+        contract.shares.insert(&alice, &U128(100));
+        contract.shares.insert(&bob, &U128(300));
+        contract.total_shares = U128(1000);
+        assert_eq!(contract.get_pending_redemption_value(), U128(0));
+
+        for (account, shares) in [(&alice, 100), (&bob, 300)] {
+            let mut context = get_context(account.clone());
+            context.attached_deposit(NearToken::from_yoctonear(1));
+            testing_env!(context.build());
+            contract.request_redemption(U128(shares));
+        }
+
+        // 40% of a fund worth 4000
+        assert_eq!(contract.get_pending_redemption_value(), U128(1600));
+        contract.process_redemptions(1);
+        assert_eq!(contract.get_pending_redemption_value(), U128(1200));
+    }
+
+    #[test]
+    fn test_rebalance_plan_excludes_queued_redemptions() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let alice = AccountId::from_str("alice.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset1, 5000), (&asset2, 5000)]);
+        set_prices(&mut contract, &[(&asset1, 1), (&asset2, 1)]);
+        set_balances(&mut contract, &[(&asset1, 600), (&asset2, 400)]);
+        contract.shares.insert(&alice, &U128(500));
+        contract.total_shares = U128(1000);
+        let plan = contract.internal_rebalance_plan();
+        assert!(plan.contains(&(asset1.clone(), -100)));
+
+        let mut context = get_context(alice.clone());
+        context.attached_deposit(NearToken::from_yoctonear(1));
+        testing_env!(context.build());
+        contract.request_redemption(U128(500));
+
+        // 300 of asset1 and 200 of asset2 are promised to alice and out of the plan
+        assert_eq!(contract.assets.get(&asset1).unwrap().balance, U128(300));
+        let plan = contract.internal_rebalance_plan();
+        assert_eq!(plan.len(), 2);
+        assert!(plan.contains(&(asset1.clone(), -50)));
+        assert!(plan.contains(&(asset2.clone(), 50)));
+    }
+
+    #[test]
+    fn test_redemption_queue_fifo() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let alice = AccountId::from_str("alice.near").unwrap();
+        let bob = AccountId::from_str("bob.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset, 10000)]);
+        set_prices(&mut contract, &[(&asset, 1)]);
+        set_balances(&mut contract, &[(&asset, 1000)]);
+        contract.shares.insert(&alice, &U128(100));
+        contract.shares.insert(&bob, &U128(200));
+        contract.total_shares = U128(1000);
+
+        let mut context = get_context(alice.clone());
+        context.attached_deposit(NearToken::from_yoctonear(1));
+        testing_env!(context.build());
+        assert_eq!(contract.request_redemption(U128(100)), 0);
+
+        let mut context = get_context(bob.clone());
+        context.attached_deposit(NearToken::from_yoctonear(1));
+        testing_env!(context.build());
+        assert_eq!(contract.request_redemption(U128(200)), 1);
+
+        // shares are burned and the claims are off the books right away
+        assert_eq!(contract.total_shares, U128(700));
+        assert_eq!(contract.assets.get(&asset).unwrap().balance, U128(700));
+
+        let queue = contract.get_redemption_queue(0, 10);
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue[0].account, alice);
+        assert_eq!(queue[0].amounts, vec![(asset.clone(), U128(100))]);
+        assert_eq!(queue[1].account, bob);
+        assert_eq!(queue[1].amounts, vec![(asset.clone(), U128(200))]);
+
+        assert_eq!(contract.process_redemptions(1), 1);
+        let queue = contract.get_redemption_queue(0, 10);
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue[0].account, bob);
+
+        assert_eq!(contract.process_redemptions(5), 1);
+        assert!(contract.get_redemption_queue(0, 10).is_empty());
+        assert_eq!(contract.redemption_queue.len(), 0);
+        assert_eq!(contract.process_redemptions(5), 0);
+    }
+
+    #[test]
+    fn test_on_redemption_transfer_failure_requeues() {
+        let alice = AccountId::from_str("alice.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+
+        let mut contract = IndexFund::with_defaults();
+        contract.on_redemption_transfer(
+            alice.clone(),
+            asset.clone(),
+            U128(100),
+            Err(PromiseError::Failed),
+        );
+
+        let queue = contract.get_redemption_queue(0, 10);
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue[0].account, alice);
+        assert_eq!(queue[0].amounts, vec![(asset, U128(100))]);
+    }
+
+    #[test]
+    fn test_on_asset_transfer_failure_restores_balance() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset, 10000)]);
+        set_balances(&mut contract, &[(&asset, 900)]);
+
+        contract.on_asset_transfer(asset.clone(), U128(100), Err(PromiseError::Failed));
+        assert_eq!(contract.assets.get(&asset).unwrap().balance, U128(1000));
+
+        contract.on_asset_transfer(asset.clone(), U128(100), Ok(()));
+        assert_eq!(contract.assets.get(&asset).unwrap().balance, U128(1000));
+    }
+
+    fn start_liquidation() -> IndexFund {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let dex = AccountId::from_str("dex.near").unwrap();
+        let old = AccountId::from_str("old.near").unwrap();
+        let usdc = AccountId::from_str("usdc.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&old, 0), (&usdc, 10000)]);
+        set_balances(&mut contract, &[(&old, 300), (&usdc, 1000)]);
+        contract.set_dex(dex);
+        contract.remove_asset_into(old.clone(), usdc);
+        let holding = contract.assets.get(&old).unwrap();
+        assert_eq!(holding.balance, U128(0));
+        assert!(!holding.deposits_enabled);
+        assert!(contract.liquidation.is_some());
+        contract
+    }
+
+    #[test]
+    fn test_remove_asset_into() {
+        let dex = AccountId::from_str("dex.near").unwrap();
+        let old = AccountId::from_str("old.near").unwrap();
+        let usdc = AccountId::from_str("usdc.near").unwrap();
+        let mut contract = start_liquidation();
+
+        // the DEX hands the proceeds back, then the transfer call resolves
+        testing_env!(get_context(usdc.clone()).build());
+        let unused = contract.ft_on_transfer(dex, U128(600), LIQUIDATION_MSG.to_string());
+        assert_eq!(expect_value(unused), U128(0));
+        contract.on_liquidate(old.clone(), U128(300), Ok(U128(300)));
+
+        assert!(!contract.is_asset(old));
+        assert_eq!(contract.assets.get(&usdc).unwrap().balance, U128(1600));
+        assert_eq!(contract.total_shares, U128(0));
+        assert_eq!(contract.liquidation, None);
+    }
+
+    #[test]
+    fn test_deposit_refunded_during_liquidation() {
+        let investor = AccountId::from_str("investor.near").unwrap();
+        let usdc = AccountId::from_str("usdc.near").unwrap();
+        let mut contract = start_liquidation();
+        set_prices(&mut contract, &[(&usdc, 1)]);
+        contract.shares.insert(&investor, &U128(0));
+
+        testing_env!(get_context(usdc.clone()).build());
+        let unused = contract.ft_on_transfer(investor.clone(), U128(100), String::new());
+        assert_eq!(expect_value(unused), U128(100));
+        assert_eq!(contract.shares.get(&investor), Some(U128(0)));
+        assert_eq!(contract.assets.get(&usdc).unwrap().balance, U128(1000));
+    }
+
+    #[test]
+    #[should_panic(expected = "A liquidation is in flight")]
+    fn test_redeem_during_liquidation() {
+        let investor = AccountId::from_str("investor.near").unwrap();
+        let mut contract = start_liquidation();
+        contract.shares.insert(&investor, &U128(100));
+        contract.total_shares = U128(100);
+        assert_eq!(
+            contract.can_redeem(investor.clone()),
+            (false, Some("A liquidation is in flight".to_string()))
+        );
+
+        let mut context = get_context(investor);
+        context.attached_deposit(NearToken::from_yoctonear(1));
+        testing_env!(context.build());
+        contract.redeem(U128(100));
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown deposit msg")]
+    fn test_liquidation_msg_for_other_token() {
+        let dex = AccountId::from_str("dex.near").unwrap();
+        let old = AccountId::from_str("old.near").unwrap();
+        let mut contract = start_liquidation();
+
+        // the asset being sold isn't what the liquidation pays out in
+        testing_env!(get_context(old).build());
+        contract.ft_on_transfer(dex, U128(600), LIQUIDATION_MSG.to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown deposit msg")]
+    fn test_liquidation_msg_without_liquidation() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let dex = AccountId::from_str("dex.near").unwrap();
+        let usdc = AccountId::from_str("usdc.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&usdc, 10000)]);
+        contract.set_dex(dex.clone());
+        testing_env!(get_context(usdc).build());
+        contract.ft_on_transfer(dex, U128(600), LIQUIDATION_MSG.to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown deposit msg")]
+    fn test_liquidation_msg_from_stranger() {
+        let stranger = AccountId::from_str("stranger.near").unwrap();
+        let usdc = AccountId::from_str("usdc.near").unwrap();
+        let mut contract = start_liquidation();
+
+        testing_env!(get_context(usdc).build());
+        contract.ft_on_transfer(stranger, U128(600), LIQUIDATION_MSG.to_string());
+    }
+
+    #[test]
+    fn test_remove_asset_into_partial_fill() {
+        let old = AccountId::from_str("old.near").unwrap();
+        let mut contract = start_liquidation();
+
+        contract.on_liquidate(old.clone(), U128(300), Ok(U128(200)));
+        assert_eq!(contract.assets.get(&old).unwrap().balance, U128(100));
+
+        contract.on_liquidate(old.clone(), U128(100), Err(PromiseError::Failed));
+        assert_eq!(contract.assets.get(&old).unwrap().balance, U128(200));
+    }
+
+    #[test]
+    #[should_panic(expected = "A liquidation is in flight")]
+    fn test_remove_asset_during_liquidation() {
+        let old = AccountId::from_str("old.near").unwrap();
+        let mut contract = start_liquidation();
+        contract.remove_asset(old);
+    }
+
+    #[test]
+    fn test_auto_prune_skips_liquidating_asset() {
+        let old = AccountId::from_str("old.near").unwrap();
+        let usdc = AccountId::from_str("usdc.near").unwrap();
+        let mut contract = start_liquidation();
+        contract.auto_prune_zero_weight = true;
+
+        contract.update_weights(vec![
+            AssetWeight {
+                asset_address: old.clone(),
+                weight: U64(0),
+            },
+            AssetWeight {
+                asset_address: usdc,
+                weight: U64(10000),
+            },
+        ]);
+        assert!(contract.is_asset(old));
+    }
+
+    #[test]
+    fn test_on_liquidate_restores_missing_holding() {
+        let old = AccountId::from_str("old.near").unwrap();
+        let mut contract = start_liquidation();
+        contract.assets.remove(&old);
+
+        contract.on_liquidate(old.clone(), U128(300), Ok(U128(200)));
+        let holding = contract.assets.get(&old).unwrap();
+        assert_eq!(holding.balance, U128(100));
+        assert_eq!(holding.weight, U64(0));
+        assert!(!holding.deposits_enabled);
+    }
+
+    fn fund_with_wrap_near(investor: &AccountId) -> IndexFund {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let wnear = AccountId::from_str("wrap.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&wnear, 10000)]);
+        set_prices(&mut contract, &[(&wnear, 2)]);
+        contract.set_wrap_near(wnear);
+        contract.shares.insert(investor, &U128(0));
+        contract
+    }
+
+    #[test]
+    fn test_deposit_native_near() {
+        let investor = AccountId::from_str("investor.near").unwrap();
+        let wnear = AccountId::from_str("wrap.near").unwrap();
+        let mut contract = fund_with_wrap_near(&investor);
+
+        let mut context = get_context(investor.clone());
+        context.attached_deposit(NearToken::from_yoctonear(500));
+        testing_env!(context.build());
+        contract.deposit();
+        // nothing is credited until the wrap resolves
+        assert_eq!(contract.assets.get(&wnear).unwrap().balance, U128(0));
+
+        let minted = contract.on_near_wrapped(investor.clone(), wnear.clone(), U128(500), Ok(()));
+        assert_eq!(minted, U128(1000));
+        assert_eq!(contract.assets.get(&wnear).unwrap().balance, U128(500));
+        assert_eq!(contract.shares.get(&investor), Some(U128(1000)));
+    }
+
+    #[test]
+    fn test_deposit_native_near_wrap_failed() {
+        let investor = AccountId::from_str("investor.near").unwrap();
+        let wnear = AccountId::from_str("wrap.near").unwrap();
+        let mut contract = fund_with_wrap_near(&investor);
+
+        let minted = contract.on_near_wrapped(
+            investor.clone(),
+            wnear.clone(),
+            U128(500),
+            Err(PromiseError::Failed),
+        );
+        assert_eq!(minted, U128(0));
+        assert_eq!(contract.assets.get(&wnear).unwrap().balance, U128(0));
+        assert_eq!(contract.total_shares, U128(0));
+    }
+
+    #[test]
+    fn test_deposit_native_near_refunds_wnear_when_cap_filled() {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        let investor = AccountId::from_str("investor.near").unwrap();
+        let wnear = AccountId::from_str("wrap.near").unwrap();
+        let mut contract = fund_with_wrap_near(&investor);
+        contract.owner_id = Some(owner.clone());
+        testing_env!(get_context(owner).build());
+        contract.set_max_share_supply(Some(U128(1001)));
+
+        let mut context = get_context(investor.clone());
+        context.attached_deposit(NearToken::from_yoctonear(500));
+        testing_env!(context.build());
+        contract.deposit();
+
+        // another deposit takes the room under the cap before the wrap resolves
+        contract.internal_deposit(&investor, &wnear, 2);
+        let minted = contract.on_near_wrapped(investor.clone(), wnear.clone(), U128(500), Ok(()));
+        assert_eq!(minted, U128(0));
+        assert_eq!(contract.assets.get(&wnear).unwrap().balance, U128(2));
+        assert_eq!(contract.total_shares, U128(4));
+        assert!(get_logs().contains(
+            &"Deposit would exceed the share supply cap, refunding the wrapped NEAR".to_string()
+        ));
+        let calls = created_calls();
+        assert!(calls.contains(&(wnear, "ft_transfer".to_string())));
+        assert!(calls.contains(&(env::current_account_id(), "on_pending_withdraw".to_string())));
+    }
+
+    #[test]
+    fn test_deposit_native_near_refunds_wnear_when_fee_fills_cap() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let investor = AccountId::from_str("investor.near").unwrap();
+        let treasury = AccountId::from_str("treasury.near").unwrap();
+        let wnear = AccountId::from_str("wrap.near").unwrap();
+        let mut contract = fund_with_wrap_near(&investor);
+        contract.owner_id = Some(curator.clone());
+        set_balances(&mut contract, &[(&wnear, 500_000)]);
+        contract.internal_mint_shares(&investor, 1_000_000);
+        contract.set_fee_recipient(treasury.clone());
+        contract.set_management_fee_bps(U64(200));
+        // room for the 1_000 shares the deposit mints now, but not once a year's fee is taken
+        contract.set_max_share_supply(Some(U128(1_011_000)));
+
+        let mut context = get_context(investor.clone());
+        context.attached_deposit(NearToken::from_yoctonear(500));
+        testing_env!(context.build());
+        contract.deposit();
+
+        let mut context = get_context(investor.clone());
+        context.block_timestamp(100 + NS_PER_YEAR);
+        testing_env!(context.build());
+        let minted = contract.on_near_wrapped(investor.clone(), wnear.clone(), U128(500), Ok(()));
+        assert_eq!(minted, U128(0));
+        assert_eq!(contract.shares.get(&treasury), Some(U128(20_000)));
+        assert_eq!(contract.shares.get(&investor), Some(U128(1_000_000)));
+        assert_eq!(contract.assets.get(&wnear).unwrap().balance, U128(500_000));
+        assert!(get_logs().contains(
+            &"Deposit would exceed the share supply cap, refunding the wrapped NEAR".to_string()
+        ));
+        assert!(created_calls().contains(&(wnear, "ft_transfer".to_string())));
+    }
+
+    #[test]
+    fn test_deposit_native_near_refunds_wnear_when_paused() {
+        let investor = AccountId::from_str("investor.near").unwrap();
+        let wnear = AccountId::from_str("wrap.near").unwrap();
+        let mut contract = fund_with_wrap_near(&investor);
+        contract.paused = true;
+
+        let minted = contract.on_near_wrapped(investor.clone(), wnear.clone(), U128(500), Ok(()));
+        assert_eq!(minted, U128(0));
+        assert_eq!(contract.total_shares, U128(0));
+        assert!(get_logs().contains(&"Contract is paused, refunding the wrapped NEAR".to_string()));
+    }
+
+    #[test]
+    #[should_panic(expected = "Wrapped NEAR not set")]
+    fn test_deposit_native_near_without_wrap() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+        let mut contract = fund_with_weights(&curator, &[(&asset, 10000)]);
+
+        let mut context = get_context(curator);
+        context.attached_deposit(NearToken::from_yoctonear(500));
+        testing_env!(context.build());
+        contract.deposit();
+    }
+
+    fn weight_of(weights: &[AssetWeight], asset_id: &AccountId) -> U64 {
+        weights
+            .iter()
+            .find(|w| w.asset_address == *asset_id)
+            .unwrap()
+            .weight
+    }
+
+    #[test]
+    fn test_effective_weights_after_fees_nothing_minted() {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+
+        let mut contract = fund_with_weights(&owner, &[(&asset1, 5000), (&asset2, 5000)]);
+        set_prices(&mut contract, &[(&asset1, 1), (&asset2, 1)]);
+        set_balances(&mut contract, &[(&asset1, 6000), (&asset2, 4000)]);
+        // a share is worth 10, so 9 buys nothing
+        contract.total_shares = U128(1000);
+
+        for deposit_value in [0, 9] {
+            let weights = contract.get_effective_weights_after_fees(U128(deposit_value));
+            assert_eq!(weights.len(), 2);
+            assert_eq!(weight_of(&weights, &asset1), U64(0));
+            assert_eq!(weight_of(&weights, &asset2), U64(0));
+        }
+    }
+
+    #[test]
+    fn test_effective_weights_after_fees() {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        let treasury = AccountId::from_str("treasury.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+
+        let mut contract = fund_with_weights(&owner, &[(&asset1, 5000), (&asset2, 5000)]);
+        contract.owner_id = Some(owner);
+        set_prices(&mut contract, &[(&asset1, 1), (&asset2, 1)]);
+        set_balances(&mut contract, &[(&asset1, 6000), (&asset2, 4000)]);
+        contract.total_shares = U128(10000);
+
+        let weights = contract.get_effective_weights_after_fees(U128(1000));
+        assert_eq!(weight_of(&weights, &asset1), U64(6000));
+        assert_eq!(weight_of(&weights, &asset2), U64(4000));
+
+        // a 1% entry fee takes 1% off the exposure to every asset
+        contract.set_fee_recipient(treasury);
+        contract.set_entry_fee_bps(U64(100));
+        let weights = contract.get_effective_weights_after_fees(U128(1000));
+        assert_eq!(weight_of(&weights, &asset1), U64(5940));
+        assert_eq!(weight_of(&weights, &asset2), U64(3960));
+    }
+
+    #[test]
+    fn test_max_share_supply() {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        let investor = AccountId::from_str("investor.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+
+        let mut contract = fund_with_weights(&owner, &[(&asset, 10000)]);
+        set_prices(&mut contract, &[(&asset, 1)]);
+        contract.shares.insert(&investor, &U128(0));
+        contract.owner_id = Some(owner);
+        contract.set_max_share_supply(Some(U128(1000)));
+
+        testing_env!(get_context(asset.clone()).build());
+        contract.ft_on_transfer(investor.clone(), U128(1000), String::new());
+        assert_eq!(contract.total_shares, U128(1000));
+    }
+
+    #[test]
+    #[should_panic(expected = "Deposit would exceed the share supply cap")]
+    fn test_max_share_supply_exceeded() {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        let investor = AccountId::from_str("investor.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+
+        let mut contract = fund_with_weights(&owner, &[(&asset, 10000)]);
+        set_prices(&mut contract, &[(&asset, 1)]);
+        contract.shares.insert(&investor, &U128(0));
+        contract.owner_id = Some(owner);
+        contract.set_max_share_supply(Some(U128(1000)));
+
+        testing_env!(get_context(asset.clone()).build());
+        contract.ft_on_transfer(investor.clone(), U128(1001), String::new());
+    }
+
+    #[test]
+    fn test_ft_on_transfer_min_deposit_value() {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        let investor = AccountId::from_str("investor.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+
+        let mut contract = fund_with_weights(&owner, &[(&asset, 10000)]);
+        set_prices(&mut contract, &[(&asset, 2)]);
+        contract.shares.insert(&investor, &U128(0));
+        contract.owner_id = Some(owner);
+        contract.set_min_deposit_value(U128(100));
+
+        testing_env!(get_context(asset.clone()).build());
+        let unused = contract.ft_on_transfer(investor.clone(), U128(49), String::new());
+        assert_eq!(expect_value(unused), U128(49));
+        assert_eq!(contract.total_shares, U128(0));
+
+        let unused = contract.ft_on_transfer(investor.clone(), U128(50), String::new());
+        assert_eq!(expect_value(unused), U128(0));
+        assert_eq!(contract.shares.get(&investor), Some(U128(100)));
+    }
+
+    #[test]
+    fn test_ft_on_transfer_refunds_disabled_asset() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let investor = AccountId::from_str("investor.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset1, 5000), (&asset2, 5000)]);
+        set_prices(&mut contract, &[(&asset1, 1), (&asset2, 1)]);
+        contract.shares.insert(&investor, &U128(0));
+        contract.set_asset_deposits_enabled(asset1.clone(), false);
+
+        testing_env!(get_context(asset1.clone()).build());
+        let unused = contract.ft_on_transfer(investor.clone(), U128(100), String::new());
+        assert_eq!(expect_value(unused), U128(100));
+        assert_eq!(contract.assets.get(&asset1).unwrap().balance, U128(0));
+        assert_eq!(contract.total_shares, U128(0));
+
+        testing_env!(get_context(asset2.clone()).build());
+        let unused = contract.ft_on_transfer(investor.clone(), U128(100), String::new());
+        assert_eq!(expect_value(unused), U128(0));
+        assert_eq!(contract.assets.get(&asset2).unwrap().balance, U128(100));
+        assert_eq!(contract.shares.get(&investor), Some(U128(100)));
+    }
+
+    #[test]
+    fn test_ft_on_transfer_respects_max_balance() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let investor = AccountId::from_str("investor.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset, 10000)]);
+        set_prices(&mut contract, &[(&asset, 1)]);
+        contract.set_asset_max_balance(asset.clone(), Some(U128(150)));
+        contract.shares.insert(&investor, &U128(0));
+
+        testing_env!(get_context(asset.clone()).build());
+        let refund = contract.ft_on_transfer(investor.clone(), U128(100), String::new());
+        assert_eq!(expect_value(refund), U128(0));
+
+        // only 50 more fit under the cap
+        let refund = contract.ft_on_transfer(investor.clone(), U128(100), String::new());
+        assert_eq!(expect_value(refund), U128(50));
+        assert_eq!(contract.assets.get(&asset).unwrap().balance, U128(150));
+        assert_eq!(contract.shares.get(&investor), Some(U128(150)));
+
+        let refund = contract.ft_on_transfer(investor.clone(), U128(10), String::new());
+        assert_eq!(expect_value(refund), U128(10));
+    }
+
+    #[test]
+    fn test_get_fees() {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        let treasury = AccountId::from_str("treasury.near").unwrap();
+        testing_env!(get_context(owner.clone()).build());
+
+        let mut contract = IndexFund::new(U64(100), None, None, None);
+        contract.set_fee_recipient(treasury.clone());
+        contract.set_entry_fee_bps(U64(50));
+        contract.set_exit_fee_bps(U64(25));
+        contract.set_management_fee_bps(U64(200));
+
+        assert_eq!(
+            contract.get_fees(),
+            FeeConfig {
+                entry_fee_bps: U64(50),
+                exit_fee_bps: U64(25),
+                management_fee_bps: U64(200),
+                keeper_reward_bps: U64(0),
+                fee_recipient: Some(treasury),
+            }
+        );
+    }
+
+    #[test]
+    fn test_accrue_management_fee() {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        let investor = AccountId::from_str("investor.near").unwrap();
+        let treasury = AccountId::from_str("treasury.near").unwrap();
+        testing_env!(get_context(owner.clone()).build());
+
+        let mut contract = IndexFund::new(U64(100), None, None, None);
+        contract.shares.insert(&investor, &U128(0));
+        contract.internal_mint_shares(&investor, 1_000_000);
+        contract.set_fee_recipient(treasury.clone());
+        contract.set_management_fee_bps(U64(200));
+
+        // half a year at 2% a year
+        let mut context = get_context(owner);
+        context.block_timestamp(100 + NS_PER_YEAR / 2);
+        testing_env!(context.build());
+        assert_eq!(contract.accrue_management_fee(), U128(10_000));
+        assert_eq!(contract.shares.get(&treasury), Some(U128(10_000)));
+
+        // nothing more is owed in the same block
+        assert_eq!(contract.accrue_management_fee(), U128(0));
+    }
+
+    #[test]
+    fn test_management_fee_deposit_mid_period() {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        let investor = AccountId::from_str("investor.near").unwrap();
+        let alice = AccountId::from_str("alice.near").unwrap();
+        let treasury = AccountId::from_str("treasury.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+
+        let mut contract = fund_with_weights(&owner, &[(&asset, 10000)]);
+        contract.owner_id = Some(owner.clone());
+        set_prices(&mut contract, &[(&asset, 1)]);
+        set_balances(&mut contract, &[(&asset, 1_000_000)]);
+        contract.shares.insert(&investor, &U128(0));
+        contract.shares.insert(&alice, &U128(0));
+        contract.internal_mint_shares(&investor, 1_000_000);
+        contract.set_fee_recipient(treasury.clone());
+        contract.set_management_fee_bps(U64(200));
+
+        // half a year in, the fee owed on the old supply is taken before alice's shares exist
+        let mut context = get_context(owner.clone());
+        context.block_timestamp(100 + NS_PER_YEAR / 2);
+        testing_env!(context.build());
+        let minted = contract.internal_deposit(&alice, &asset, 1_000_000);
+        assert_eq!(contract.shares.get(&treasury), Some(U128(10_000)));
+        assert_eq!(minted, 1_010_000);
+
+        // the second half is charged on the full 2_020_000 supply
+        let mut context = get_context(owner);
+        context.block_timestamp(100 + NS_PER_YEAR);
+        testing_env!(context.build());
+        assert_eq!(contract.accrue_management_fee(), U128(20_200));
+        assert_eq!(contract.shares.get(&treasury), Some(U128(30_200)));
+    }
+
+    // Two deposits of 3:1 value into a fresh fund with the given share decimals
+    fn mint_with_share_decimals(share_decimals: u8) -> (u128, u128) {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        let alice = AccountId::from_str("alice.near").unwrap();
+        let bob = AccountId::from_str("bob.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+
+        let mut contract = fund_with_weights(&owner, &[(&asset, 10000)]);
+        contract.owner_id = Some(owner);
+        contract.set_share_decimals(share_decimals);
+        assert_eq!(contract.ft_metadata().decimals, share_decimals);
+        set_prices(&mut contract, &[(&asset, 1)]);
+        contract.shares.insert(&alice, &U128(0));
+        contract.shares.insert(&bob, &U128(0));
+        contract.internal_deposit(&alice, &asset, 3_000_000_000_000);
+        contract.internal_deposit(&bob, &asset, 1_000_000_000_000);
+        (
+            contract.shares.get(&alice).unwrap().0,
+            contract.shares.get(&bob).unwrap().0,
+        )
+    }
+
+    #[test]
+    fn test_share_decimals_default() {
+        assert_eq!(
+            mint_with_share_decimals(24),
+            (3_000_000_000_000, 1_000_000_000_000)
+        );
+    }
+
+    #[test]
+    fn test_share_decimals_18() {
+        // six fewer decimals mint a millionth of the units, in the same proportions
+        assert_eq!(mint_with_share_decimals(18), (3_000_000, 1_000_000));
+    }
+
+    fn set_balances(contract: &mut IndexFund, balances: &[(&AccountId, u128)]) {
+        for (asset_id, balance) in balances {
+            let mut holding = contract.assets.get(asset_id).expect("Asset not found");
+            holding.balance = U128(*balance);
+            contract.assets.insert(asset_id, &holding);
+        }
+    }
+
+    fn park(contract: &mut IndexFund, investor: &AccountId, asset_id: &AccountId, amount: u128) {
+        testing_env!(get_context(asset_id.clone()).build());
+        contract.ft_on_transfer(
+            investor.clone(),
+            U128(amount),
+            PROPORTIONAL_DEPOSIT_MSG.to_string(),
+        );
+    }
+
+    #[test]
+    fn test_deposit_proportional() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let investor = AccountId::from_str("investor.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset1, 5000), (&asset2, 5000)]);
+        set_prices(&mut contract, &[(&asset1, 1), (&asset2, 2)]);
+        set_balances(&mut contract, &[(&asset1, 100), (&asset2, 50)]);
+        contract.total_shares = U128(200);
+        contract.shares.insert(&investor, &U128(0));
+
+        park(&mut contract, &investor, &asset1, 10);
+        park(&mut contract, &investor, &asset2, 5);
+        assert_eq!(
+            contract.get_pending_deposit(investor.clone(), asset1.clone()),
+            U128(10)
+        );
+
+        testing_env!(get_context(investor.clone()).build());
+        let minted = contract
+            .deposit_proportional(vec![(asset1.clone(), U128(10)), (asset2.clone(), U128(5))]);
+        assert_eq!(minted, U128(20));
+        assert_eq!(contract.shares.get(&investor), Some(U128(20)));
+        assert_eq!(contract.assets.get(&asset1).unwrap().balance, U128(110));
+        assert_eq!(contract.assets.get(&asset2).unwrap().balance, U128(55));
+        assert_eq!(contract.get_pending_deposit(investor, asset1), U128(0));
+    }
+
+    #[test]
+    #[should_panic(expected = "Deposit proportions don't match the portfolio")]
+    fn test_deposit_proportional_skewed() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let investor = AccountId::from_str("investor.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset1, 5000), (&asset2, 5000)]);
+        set_prices(&mut contract, &[(&asset1, 1), (&asset2, 2)]);
+        set_balances(&mut contract, &[(&asset1, 100), (&asset2, 50)]);
+        contract.total_shares = U128(200);
+        contract.shares.insert(&investor, &U128(0));
+
+        park(&mut contract, &investor, &asset1, 10);
+        park(&mut contract, &investor, &asset2, 1);
+
+        testing_env!(get_context(investor).build());
+        contract.deposit_proportional(vec![(asset1, U128(10)), (asset2, U128(1))]);
+    }
+
+    #[test]
+    fn test_pause_and_unpause_emit_events() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+
+        testing_env!(get_context(curator.clone()).build());
+
+        let mut contract = IndexFund::with_defaults();
+        contract.curator_address = Some(curator);
+
+        contract.pause();
+        assert!(contract.paused);
+        assert_eq!(
+            get_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"index_fund","version":"1.0.0","event":"paused","data":{"by":"curator.near"}}"#
+            ]
+        );
+
+        contract.unpause();
+        assert!(!contract.paused);
+        assert_eq!(
+            get_logs()[1],
+            r#"EVENT_JSON:{"standard":"index_fund","version":"1.0.0","event":"unpaused","data":{"by":"curator.near"}}"#
+        );
+    }
+
+    // Pauses and unpauses a funded fund with a 1000ns grace period; the clock is then at 200
+    fn fund_in_unpause_grace(investor: &AccountId) -> IndexFund {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset, 10000)]);
+        contract.owner_id = Some(curator.clone());
+        contract.set_unpause_grace_ns(U64(1000));
+        set_balances(&mut contract, &[(&asset, 500)]);
+        contract.shares.insert(investor, &U128(100));
+        contract.total_shares = U128(100);
+        contract.pause();
+        contract.unpause();
+
+        let mut context = get_context(investor.clone());
+        context.attached_deposit(NearToken::from_yoctonear(1));
+        context.block_timestamp(200);
+        testing_env!(context.build());
+        contract
+    }
+
+    #[test]
+    fn test_redeem_during_unpause_grace() {
+        let investor = AccountId::from_str("investor.near").unwrap();
+        let mut contract = fund_in_unpause_grace(&investor);
+        let payouts = contract.redeem(U128(50));
+        assert_eq!(payouts[0].1, U128(250));
+    }
+
+    #[test]
+    #[should_panic(expected = "Contract is in its post-unpause grace period")]
+    fn test_update_weights_during_unpause_grace() {
+        let investor = AccountId::from_str("investor.near").unwrap();
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+        let mut contract = fund_in_unpause_grace(&investor);
+
+        let mut context = get_context(curator);
+        context.block_timestamp(1099);
+        testing_env!(context.build());
         contract.update_weights(vec![AssetWeight {
-            weight: U64(5000), // Only 50% instead of required 100%
+            weight: U64(10000),
+            asset_address: asset,
+        }]);
+    }
+
+    #[test]
+    fn test_update_weights_after_unpause_grace() {
+        let investor = AccountId::from_str("investor.near").unwrap();
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+        let mut contract = fund_in_unpause_grace(&investor);
+
+        let mut context = get_context(curator);
+        context.block_timestamp(1100);
+        testing_env!(context.build());
+        contract.update_weights(vec![AssetWeight {
+            weight: U64(10000),
+            asset_address: asset,
+        }]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Contract is paused")]
+    fn test_update_weights_while_paused() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset, 10000)]);
+        contract.pause();
+
+        contract.update_weights(vec![AssetWeight {
+            weight: U64(10000),
             asset_address: asset,
         }]);
     }
+
+    #[test]
+    fn test_ft_on_transfer_refunds_while_paused() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let investor = AccountId::from_str("investor.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset, 10000)]);
+        set_prices(&mut contract, &[(&asset, 1)]);
+        contract.shares.insert(&investor, &U128(0));
+        contract.pause();
+
+        testing_env!(get_context(asset.clone()).build());
+        let unused = contract.ft_on_transfer(investor, U128(100), String::new());
+        assert_eq!(expect_value(unused), U128(100));
+        assert_eq!(contract.total_shares, U128(0));
+    }
+
+    #[test]
+    #[should_panic(expected = "Unauthorized")]
+    fn test_pause_unauthorized() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let stranger = AccountId::from_str("stranger.near").unwrap();
+
+        testing_env!(get_context(stranger).build());
+
+        let mut contract = IndexFund::with_defaults();
+        contract.curator_address = Some(curator);
+        contract.pause();
+    }
+
+    #[test]
+    fn test_new_sets_owner() {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        testing_env!(get_context(owner.clone()).build());
+
+        let contract = IndexFund::new(U64(100), None, None, None);
+        assert_eq!(contract.owner_id, Some(owner));
+        assert_eq!(contract.rebalance_interval, U64(100));
+    }
+
+    #[test]
+    fn test_renounce_ownership() {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        testing_env!(get_context(owner.clone()).build());
+
+        let mut contract = IndexFund::new(U64(100), None, None, None);
+        contract.renounce_ownership(env::current_account_id().to_string());
+        assert_eq!(contract.owner_id, None);
+        assert_eq!(
+            get_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"index_fund","version":"1.0.0","event":"ownership_renounced","data":{"previous_owner":"owner.near"}}"#
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Confirmation must be the contract account id")]
+    fn test_renounce_ownership_wrong_confirmation() {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        testing_env!(get_context(owner.clone()).build());
+
+        let mut contract = IndexFund::new(U64(100), None, None, None);
+        contract.renounce_ownership("yes".to_string());
+    }
+
+    fn fund_after_weight_commit(
+        curator: &AccountId,
+        asset: &AccountId,
+    ) -> (IndexFund, Vec<AssetWeight>) {
+        let old = AccountId::from_str("old.near").unwrap();
+        let mut contract = fund_with_weights(curator, &[(&old, 10000)]);
+
+        let updates = vec![
+            AssetWeight {
+                weight: U64(0),
+                asset_address: old,
+            },
+            AssetWeight {
+                weight: U64(10000),
+                asset_address: asset.clone(),
+            },
+        ];
+        let hash = contract.hash_weights(updates.clone(), "pepper".to_string());
+        contract.commit_weights(hash);
+
+        let mut context = get_context(curator.clone());
+        context.block_timestamp(100 + WEIGHT_REVEAL_DELAY_NS);
+        testing_env!(context.build());
+        (contract, updates)
+    }
+
+    #[test]
+    fn test_reveal_weights() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+        let (mut contract, updates) = fund_after_weight_commit(&curator, &asset);
+
+        contract.reveal_weights(updates, "pepper".to_string());
+        assert_eq!(contract.assets.get(&asset).unwrap().weight, U64(10000));
+        assert_eq!(contract.weight_commitment, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Weights don't match the commitment")]
+    fn test_reveal_weights_wrong_salt() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+        let (mut contract, updates) = fund_after_weight_commit(&curator, &asset);
+
+        contract.reveal_weights(updates, "salt".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Reveal delay has not elapsed")]
+    fn test_reveal_weights_too_early() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+        let (mut contract, updates) = fund_after_weight_commit(&curator, &asset);
+
+        testing_env!(get_context(curator).build());
+        contract.reveal_weights(updates, "pepper".to_string());
+    }
+
+    #[test]
+    fn test_configured_event_standard() {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        testing_env!(get_context(owner).build());
+
+        let mut contract = IndexFund::new(
+            U64(100),
+            None,
+            Some("blue_chip_fund".to_string()),
+            Some("2.1.0".to_string()),
+        );
+        contract.set_last_rebalance(U64(0));
+        assert_eq!(
+            get_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"blue_chip_fund","version":"2.1.0","event":"last_rebalance_set","data":{"old":"0","new":"0"}}"#
+            ]
+        );
+    }
+
+    fn fund_after_upgrade_proposal(code: &[u8]) -> IndexFund {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        testing_env!(get_context(owner).build());
+
+        let mut contract = IndexFund::new(U64(100), None, None, None);
+        contract.propose_upgrade(
+            Base58CryptoHash::from(env::sha256_array(code)),
+            U64(100 + MIN_UPGRADE_DELAY_NS),
+        );
+        contract
+    }
+
+    #[test]
+    fn test_do_upgrade() {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        let mut contract = fund_after_upgrade_proposal(b"new code");
+
+        let mut context = get_context(owner);
+        context.block_timestamp(100 + MIN_UPGRADE_DELAY_NS);
+        testing_env!(context.build());
+        contract.do_upgrade(b"new code".to_vec());
+        assert_eq!(contract.proposed_upgrade, None);
+        assert_eq!(
+            created_calls(),
+            vec![(env::current_account_id(), "migrate".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_migrate() {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+        let mut contract = fund_with_weights(&owner, &[(&asset, 10000)]);
+        contract.owner_id = Some(owner.clone());
+        env::state_write(&contract);
+
+        let migrated = IndexFund::migrate();
+        assert_eq!(migrated.owner_id, Some(owner));
+        assert_eq!(migrated.assets.get(&asset).unwrap().weight, U64(10000));
+    }
+
+    #[test]
+    #[should_panic(expected = "Upgrade timelock has not elapsed")]
+    fn test_do_upgrade_too_early() {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        let mut contract = fund_after_upgrade_proposal(b"new code");
+
+        let mut context = get_context(owner);
+        context.block_timestamp(99 + MIN_UPGRADE_DELAY_NS);
+        testing_env!(context.build());
+        contract.do_upgrade(b"new code".to_vec());
+    }
+
+    #[test]
+    #[should_panic(expected = "Code doesn't match the proposed upgrade")]
+    fn test_do_upgrade_wrong_code() {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        let mut contract = fund_after_upgrade_proposal(b"new code");
+
+        let mut context = get_context(owner);
+        context.block_timestamp(100 + MIN_UPGRADE_DELAY_NS);
+        testing_env!(context.build());
+        contract.do_upgrade(b"other code".to_vec());
+    }
+
+    #[test]
+    fn test_get_age_ns() {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        testing_env!(get_context(owner.clone()).build());
+
+        let contract = IndexFund::new(U64(100), None, None, None);
+        assert_eq!(contract.get_inception(), U64(100));
+        assert_eq!(contract.get_age_ns(), U64(0));
+
+        let mut context = get_context(owner);
+        context.block_timestamp(5_000);
+        testing_env!(context.build());
+        assert_eq!(contract.get_age_ns(), U64(4_900));
+        assert_eq!(contract.get_inception(), U64(100));
+    }
+
+    #[test]
+    fn test_set_last_rebalance() {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        let mut context = get_context(owner.clone());
+        context.block_height(1000);
+        testing_env!(context.build());
+
+        let mut contract = IndexFund::with_defaults();
+        contract.owner_id = Some(owner);
+
+        contract.set_last_rebalance(U64(900));
+        assert_eq!(contract.last_rebalance, U64(900));
+        assert_eq!(
+            get_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"index_fund","version":"1.0.0","event":"last_rebalance_set","data":{"old":"0","new":"900"}}"#
+            ]
+        );
+
+        // the current block is still allowed
+        contract.set_last_rebalance(U64(1000));
+        assert_eq!(contract.last_rebalance, U64(1000));
+    }
+
+    #[test]
+    #[should_panic(expected = "Last rebalance can't be in the future")]
+    fn test_set_last_rebalance_future() {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        let mut context = get_context(owner.clone());
+        context.block_height(1000);
+        testing_env!(context.build());
+
+        let mut contract = IndexFund::with_defaults();
+        contract.owner_id = Some(owner);
+        contract.set_last_rebalance(U64(1001));
+    }
+
+    #[test]
+    #[should_panic(expected = "Unauthorized")]
+    fn test_set_last_rebalance_unauthorized() {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        let curator = AccountId::from_str("curator.near").unwrap();
+        testing_env!(get_context(curator.clone()).build());
+
+        let mut contract = IndexFund::with_defaults();
+        contract.owner_id = Some(owner);
+        contract.curator_address = Some(curator);
+        contract.set_last_rebalance(U64(0));
+    }
+
+    #[test]
+    fn test_collections_do_not_overlap() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let investor = AccountId::from_str("investor.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset1, 6000), (&asset2, 4000)]);
+        contract.shares.insert(&investor, &U128(10));
+        contract
+            .pending_deposits
+            .insert(&(investor.clone(), asset1.clone()), &U128(20));
+        contract.redemption_queue.push(&RedemptionRequest {
+            account: investor.clone(),
+            amounts: vec![(asset2.clone(), U128(30))],
+            requested_at: U64(0),
+        });
+
+        assert_eq!(contract.assets.len(), 2);
+        assert_eq!(contract.assets.get(&asset1).unwrap().weight, U64(6000));
+        assert_eq!(contract.assets.get(&asset2).unwrap().weight, U64(4000));
+        assert_eq!(contract.shares.len(), 1);
+        assert_eq!(contract.shares.get(&investor), Some(U128(10)));
+        assert_eq!(
+            contract.get_pending_deposit(investor.clone(), asset1),
+            U128(20)
+        );
+        assert_eq!(contract.get_redemption_queue(0, 10)[0].account, investor);
+        assert_eq!(
+            contract.get_redemption_queue(0, 10)[0].amounts[0].1,
+            U128(30)
+        );
+    }
+
+    #[test]
+    fn test_min_deposit_for_one_share() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let investor = AccountId::from_str("investor.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset, 10000)]);
+        assert_eq!(contract.min_deposit_for_one_share(), U128(1));
+
+        // 10 shares backing 10005 quote units: each share is worth just over 1000
+        set_prices(&mut contract, &[(&asset, 1)]);
+        set_balances(&mut contract, &[(&asset, 10005)]);
+        contract.shares.insert(&investor, &U128(10));
+        contract.total_shares = U128(10);
+        let threshold = contract.min_deposit_for_one_share();
+        assert_eq!(threshold, U128(1001));
+        assert_eq!(contract.shares_for_value(threshold.0), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Deposit too small to mint shares")]
+    fn test_deposit_below_one_share() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset, 10000)]);
+        set_prices(&mut contract, &[(&asset, 1)]);
+        set_balances(&mut contract, &[(&asset, 10005)]);
+        contract.total_shares = U128(10);
+        contract.shares_for_value(1000);
+    }
+
+    #[test]
+    fn test_action_history() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset1, 5000), (&asset2, 5000)]);
+        let mut context = get_context(curator.clone());
+        context.block_height(7);
+        testing_env!(context.build());
+        contract.pause();
+
+        assert_eq!(
+            contract.get_action_history(0, 10),
+            vec![
+                ActionRecord {
+                    action: ActionKind::UpdateWeights,
+                    block_height: U64(0),
+                    caller: curator.clone(),
+                },
+                ActionRecord {
+                    action: ActionKind::Pause,
+                    block_height: U64(7),
+                    caller: curator.clone(),
+                },
+            ]
+        );
+        assert_eq!(contract.get_action_history(1, 10).len(), 1);
+    }
+
+    #[test]
+    fn test_action_history_is_bounded() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset, 10000)]);
+        for i in 0..MAX_ACTION_HISTORY + 1 {
+            if i % 100 == 0 {
+                testing_env!(get_context(curator.clone()).build());
+            }
+            if contract.paused {
+                contract.unpause();
+            } else {
+                contract.pause();
+            }
+        }
+
+        // the initial weight update has been overwritten
+        assert_eq!(contract.curator_actions.len(), MAX_ACTION_HISTORY);
+        let history = contract.get_action_history(0, MAX_ACTION_HISTORY);
+        assert_eq!(history[0].action, ActionKind::Unpause);
+        assert_eq!(history.last().unwrap().action, ActionKind::Pause);
+    }
+
+    #[test]
+    fn test_nav_history() {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let keeper = AccountId::from_str("keeper.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset, 10000)]);
+        set_prices(&mut contract, &[(&asset, 1)]);
+        set_balances(&mut contract, &[(&asset, 1000)]);
+        contract.total_shares = U128(1000);
+        contract.owner_id = Some(owner.clone());
+
+        testing_env!(get_context(owner).build());
+        contract.set_keeper(keeper.clone());
+
+        for (height, price) in [(10, 1), (20, 2), (30, 3)] {
+            let mut context = get_context(keeper.clone());
+            context.block_height(height);
+            testing_env!(context.build());
+            set_prices(&mut contract, &[(&asset, price)]);
+            contract.snapshot_nav();
+        }
+
+        let page = contract.get_nav_history(0, 2);
+        assert_eq!(page.len(), 2);
+        assert_eq!(page[0].block_height, U64(10));
+        assert_eq!(page[0].nav, U128(1000));
+        assert_eq!(page[0].nav_per_share, U128(NAV_PER_SHARE_SCALE));
+        assert_eq!(page[1].block_height, U64(20));
+        assert_eq!(page[1].nav_per_share, U128(2 * NAV_PER_SHARE_SCALE));
+
+        let page = contract.get_nav_history(2, 2);
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].block_height, U64(30));
+        assert_eq!(page[0].nav, U128(3000));
+    }
+
+    #[test]
+    fn test_share_price_history() {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+
+        let mut contract = fund_with_weights(&owner, &[(&asset, 10000)]);
+        set_balances(&mut contract, &[(&asset, 1000)]);
+        contract.total_shares = U128(1000);
+        contract.owner_id = Some(owner.clone());
+
+        for (timestamp, price) in [(10, 1), (20, 3), (30, 2)] {
+            let mut context = get_context(owner.clone());
+            context.block_timestamp(timestamp);
+            testing_env!(context.build());
+            set_prices(&mut contract, &[(&asset, price)]);
+            contract.snapshot_nav();
+        }
+
+        let history = contract.get_share_price_history(0, 10);
+        assert_eq!(
+            history.iter().map(|p| p.index).collect::<Vec<_>>(),
+            vec![0, 1, 2]
+        );
+        assert_eq!(history[1].timestamp, U64(20));
+        assert_eq!(history[1].nav_per_share, U128(3 * NAV_PER_SHARE_SCALE));
+
+        let page = contract.get_share_price_history(1, 10);
+        assert_eq!(page[0].index, 1);
+        assert_eq!(page[1].index, 2);
+        assert_eq!(page[1].nav_per_share, U128(2 * NAV_PER_SHARE_SCALE));
+    }
+
+    #[test]
+    fn test_nav_history_is_bounded() {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+
+        let mut contract = fund_with_weights(&owner, &[(&asset, 10000)]);
+        set_balances(&mut contract, &[(&asset, 1)]);
+        contract.owner_id = Some(owner.clone());
+
+        // tag each snapshot with its own NAV
+        for nav in 0..MAX_NAV_HISTORY as u128 + 2 {
+            // a fresh context per snapshot is slow, but one context runs out of gas
+            if nav % 100 == 0 {
+                testing_env!(get_context(owner.clone()).build());
+            }
+            set_prices(&mut contract, &[(&asset, nav)]);
+            contract.snapshot_nav();
+        }
+
+        // the two oldest snapshots were overwritten
+        assert_eq!(contract.nav_history.len(), MAX_NAV_HISTORY);
+        let first = contract.get_nav_history(0, 1);
+        assert_eq!(first[0].nav, U128(2));
+        let last = contract.get_nav_history(MAX_NAV_HISTORY - 1, 1);
+        assert_eq!(last[0].nav, U128(MAX_NAV_HISTORY as u128 + 1));
+    }
+
+    #[test]
+    #[should_panic(expected = "Unauthorized")]
+    fn test_snapshot_nav_unauthorized() {
+        let stranger = AccountId::from_str("stranger.near").unwrap();
+        testing_env!(get_context(stranger).build());
+
+        let mut contract = IndexFund::with_defaults();
+        contract.snapshot_nav();
+    }
+
+    #[test]
+    fn test_set_rebalance_interval() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset, 10000)]);
+        contract.set_rebalance_interval(U64(MIN_REBALANCE_INTERVAL_BLOCKS));
+        assert_eq!(
+            contract.rebalance_interval,
+            U64(MIN_REBALANCE_INTERVAL_BLOCKS)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Rebalance interval below the minimum")]
+    fn test_set_rebalance_interval_below_floor() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset, 10000)]);
+        contract.set_rebalance_interval(U64(1));
+    }
+
+    #[test]
+    fn test_set_interval_unit() {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        let mut context = get_context(owner.clone());
+        context.block_height(1000);
+        context.block_timestamp(5_000_000 * 1_000_000); // 5,000,000 ms
+        testing_env!(context.build());
+
+        let mut contract = IndexFund::with_defaults();
+        contract.owner_id = Some(owner);
+        contract.rebalance_interval = U64(100);
+        contract.last_rebalance = U64(960); // 40% of the interval has elapsed
+        assert_eq!(contract.get_next_rebalance(), U64(1060));
+
+        contract.set_interval_unit(IntervalUnit::Milliseconds);
+        assert_eq!(contract.interval_unit, IntervalUnit::Milliseconds);
+        assert_eq!(contract.rebalance_interval, U64(100_000));
+        assert_eq!(contract.last_rebalance, U64(4_960_000));
+        // still 60 seconds (~60 blocks) away, not immediately due or far in the future
+        assert_eq!(contract.get_next_rebalance(), U64(5_060_000));
+
+        contract.set_interval_unit(IntervalUnit::Blocks);
+        assert_eq!(contract.rebalance_interval, U64(100));
+        assert_eq!(contract.last_rebalance, U64(960));
+        assert_eq!(contract.get_next_rebalance(), U64(1060));
+    }
+
+    #[test]
+    fn test_set_last_rebalance_in_milliseconds() {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        let mut context = get_context(owner.clone());
+        context.block_height(1000);
+        context.block_timestamp(5_000_000 * 1_000_000);
+        testing_env!(context.build());
+
+        let mut contract = IndexFund::with_defaults();
+        contract.owner_id = Some(owner);
+        contract.set_interval_unit(IntervalUnit::Milliseconds);
+
+        // far past the current block height, but not the current timestamp
+        contract.set_last_rebalance(U64(4_000_000));
+        assert_eq!(contract.last_rebalance, U64(4_000_000));
+    }
+
+    #[test]
+    fn test_rebalance() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset1, 5000), (&asset2, 5000)]);
+        set_prices(&mut contract, &[(&asset1, 3), (&asset2, 1)]);
+        set_balances(&mut contract, &[(&asset1, 100), (&asset2, 100)]);
+        contract.rebalance_interval = U64(100);
+        assert_eq!(contract.get_rebalance_count(), U64(0));
+
+        let mut context = get_context(curator.clone());
+        context.block_height(100);
+        testing_env!(context.build());
+
+        let trades = contract.rebalance();
+        assert_eq!(trades.len(), 2);
+        assert!(trades.contains(&(asset1.clone(), -100)));
+        assert!(trades.contains(&(asset2.clone(), 100)));
+        assert_eq!(contract.last_rebalance, U64(100));
+        assert_eq!(contract.get_rebalance_count(), U64(1));
+
+        for height in [200, 350] {
+            let mut context = get_context(curator.clone());
+            context.block_height(height);
+            testing_env!(context.build());
+            contract.rebalance();
+        }
+        assert_eq!(contract.get_rebalance_count(), U64(3));
+        assert_eq!(contract.last_rebalance, U64(350));
+    }
+
+    fn fund_with_keeper_reward(keeper: &AccountId) -> IndexFund {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+
+        let mut contract = fund_with_weights(&owner, &[(&asset1, 5000), (&asset2, 5000)]);
+        set_prices(&mut contract, &[(&asset1, 1), (&asset2, 1)]);
+        contract.total_shares = U128(10000);
+        contract.owner_id = Some(owner);
+        contract.rebalance_interval = U64(0);
+        contract.set_keeper(keeper.clone());
+        contract.set_keeper_reward_bps(U64(10));
+        contract.shares.insert(keeper, &U128(0));
+        testing_env!(get_context(keeper.clone()).build());
+        contract
+    }
+
+    #[test]
+    fn test_keeper_reward_for_needed_rebalance() {
+        let keeper = AccountId::from_str("keeper.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+        let mut contract = fund_with_keeper_reward(&keeper);
+        set_balances(&mut contract, &[(&asset1, 300), (&asset2, 100)]);
+
+        contract.rebalance();
+        assert_eq!(contract.shares.get(&keeper), Some(U128(10)));
+        assert_eq!(contract.total_shares, U128(10010));
+    }
+
+    #[test]
+    fn test_keeper_reward_counted_as_fee() {
+        let keeper = AccountId::from_str("keeper.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+        let mut contract = fund_with_keeper_reward(&keeper);
+        set_balances(&mut contract, &[(&asset1, 30_000), (&asset2, 10_000)]);
+
+        contract.rebalance();
+        // 10 of the 10_010 shares of a 40_000 NAV
+        assert_eq!(contract.get_total_fees_collected(), U128(39));
+        assert_eq!(contract.get_fees().keeper_reward_bps, U64(10));
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid reward")]
+    fn test_keeper_reward_above_max() {
+        let keeper = AccountId::from_str("keeper.near").unwrap();
+        let owner = AccountId::from_str("owner.near").unwrap();
+        let mut contract = fund_with_keeper_reward(&keeper);
+
+        testing_env!(get_context(owner).build());
+        contract.set_keeper_reward_bps(U64(MAX_KEEPER_REWARD_BPS + 1));
+    }
+
+    #[test]
+    fn test_no_keeper_reward_for_noop_rebalance() {
+        let keeper = AccountId::from_str("keeper.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+        let mut contract = fund_with_keeper_reward(&keeper);
+        set_balances(&mut contract, &[(&asset1, 100), (&asset2, 100)]);
+
+        contract.rebalance();
+        assert_eq!(contract.shares.get(&keeper), Some(U128(0)));
+        assert_eq!(contract.total_shares, U128(10000));
+    }
+
+    #[test]
+    fn test_get_assets_by_drift() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+        let asset3 = AccountId::from_str("asset3.near").unwrap();
+
+        let mut contract = fund_with_weights(
+            &curator,
+            &[(&asset1, 4000), (&asset2, 3000), (&asset3, 3000)],
+        );
+        set_prices(&mut contract, &[(&asset1, 1), (&asset2, 1), (&asset3, 1)]);
+        // 41% / 15% / 44%
+        set_balances(
+            &mut contract,
+            &[(&asset1, 410), (&asset2, 150), (&asset3, 440)],
+        );
+
+        assert_eq!(
+            contract.get_assets_by_drift(),
+            vec![(asset2, -1500), (asset3, 1400), (asset1, 100)]
+        );
+    }
+
+    #[test]
+    fn test_is_rebalance_overdue() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset1, 5000), (&asset2, 5000)]);
+        assert!(!contract.is_rebalance_overdue(U64(0)));
+        set_prices(&mut contract, &[(&asset1, 1), (&asset2, 1)]);
+        // 53% / 47%, 300 bps off target
+        set_balances(&mut contract, &[(&asset1, 530), (&asset2, 470)]);
+
+        assert!(contract.is_rebalance_overdue(U64(299)));
+        assert!(!contract.is_rebalance_overdue(U64(300)));
+        assert!(!contract.is_rebalance_overdue(U64(301)));
+    }
+
+    #[test]
+    fn test_tracking_error() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+        let asset3 = AccountId::from_str("asset3.near").unwrap();
+
+        let mut contract = fund_with_weights(
+            &curator,
+            &[(&asset1, 5000), (&asset2, 3000), (&asset3, 2000)],
+        );
+        assert_eq!(contract.get_tracking_error_bps(), U64(0));
+        set_prices(&mut contract, &[(&asset1, 1), (&asset2, 1), (&asset3, 1)]);
+        set_balances(
+            &mut contract,
+            &[(&asset1, 500), (&asset2, 300), (&asset3, 200)],
+        );
+        assert_eq!(contract.get_tracking_error_bps(), U64(0));
+
+        // 60/30/10 against 50/30/20: sqrt(1000^2 + 0 + 1000^2)
+        set_balances(
+            &mut contract,
+            &[(&asset1, 600), (&asset2, 300), (&asset3, 100)],
+        );
+        assert_eq!(contract.get_tracking_error_bps(), U64(1414));
+    }
+
+    #[test]
+    fn test_nav_with_prices() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset1, 5000), (&asset2, 5000)]);
+        set_prices(&mut contract, &[(&asset1, 2), (&asset2, 1)]);
+        set_balances(&mut contract, &[(&asset1, 100), (&asset2, 300)]);
+        assert_eq!(contract.get_nav(), U128(500));
+
+        // asset1 halves: 100 * 1 + 300 * 1
+        let nav = contract.nav_with_prices(vec![(asset1.clone(), U128(1))]);
+        assert_eq!(nav, U128(400));
+        assert_eq!(contract.get_nav(), U128(500));
+        assert_eq!(contract.assets.get(&asset1).unwrap().last_price, U128(2));
+    }
+
+    #[test]
+    fn test_trades_for_weights() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset1, 6000), (&asset2, 4000)]);
+        set_prices(&mut contract, &[(&asset1, 1), (&asset2, 2)]);
+        set_balances(&mut contract, &[(&asset1, 600), (&asset2, 200)]);
+        assert!(contract.internal_rebalance_plan().is_empty());
+
+        let trades = contract.trades_for_weights(vec![
+            AssetWeight {
+                weight: U64(4000),
+                asset_address: asset1.clone(),
+            },
+            AssetWeight {
+                weight: U64(6000),
+                asset_address: asset2.clone(),
+            },
+        ]);
+        assert_eq!(trades.len(), 2);
+        assert!(trades.contains(&(asset1.clone(), -200)));
+        assert!(trades.contains(&(asset2.clone(), 200)));
+        // nothing changed
+        assert_eq!(contract.assets.get(&asset1).unwrap().weight, U64(6000));
+    }
+
+    #[test]
+    fn test_rebalance_plan_band_mode() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+        let asset3 = AccountId::from_str("asset3.near").unwrap();
+
+        // 60/25/15 of NAV against 50/30/20 targets
+        let mut contract = fund_with_weights(
+            &curator,
+            &[(&asset1, 5000), (&asset2, 3000), (&asset3, 2000)],
+        );
+        set_prices(&mut contract, &[(&asset1, 1), (&asset2, 1), (&asset3, 1)]);
+        set_balances(
+            &mut contract,
+            &[(&asset1, 6000), (&asset2, 2500), (&asset3, 1500)],
+        );
+
+        let plan = contract.internal_rebalance_plan();
+        assert_eq!(plan.len(), 3);
+        assert!(plan.contains(&(asset1.clone(), -1000)));
+        assert!(plan.contains(&(asset2.clone(), 500)));
+        assert!(plan.contains(&(asset3.clone(), 500)));
+
+        // asset2 and asset3 are within ±600 bps; asset1 is only brought down to 56%
+        contract.set_rebalance_mode(RebalanceMode::Band {
+            width_bps: U64(600),
+        });
+        let plan = contract.internal_rebalance_plan();
+        assert_eq!(plan, vec![(asset1, -400)]);
+    }
+
+    #[test]
+    fn test_rebalance_plan_fee_on_transfer() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset1, 5000), (&asset2, 5000)]);
+        set_prices(&mut contract, &[(&asset1, 1), (&asset2, 1)]);
+        set_balances(&mut contract, &[(&asset1, 3000), (&asset2, 1000)]);
+        let plan = contract.internal_rebalance_plan();
+        assert!(plan.contains(&(asset2.clone(), 1000)));
+
+        // 1% of the buy is expected to go to the token's transfer fee
+        contract.set_asset_fee_on_transfer(asset2.clone(), true);
+        let plan = contract.internal_rebalance_plan();
+        assert!(plan.contains(&(asset1.clone(), -1000)));
+        assert!(plan.contains(&(asset2.clone(), 1011)));
+    }
+
+    #[test]
+    fn test_rebalance_skips_locked_assets() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+        let locked = AccountId::from_str("locked.near").unwrap();
+
+        let mut contract = fund_with_weights(
+            &curator,
+            &[(&asset1, 2500), (&asset2, 2500), (&locked, 5000)],
+        );
+        set_prices(&mut contract, &[(&asset1, 1), (&asset2, 1), (&locked, 1)]);
+        set_balances(
+            &mut contract,
+            &[(&asset1, 300), (&asset2, 100), (&locked, 100)],
+        );
+        contract.rebalance_interval = U64(1);
+        contract.set_tradeable_after(locked.clone(), U64(1000));
+
+        // the locked asset stays put and the others split their 400 evenly
+        let mut context = get_context(curator.clone());
+        context.block_height(1);
+        context.block_timestamp(999);
+        testing_env!(context.build());
+        let trades = contract.rebalance();
+        assert_eq!(trades.len(), 2);
+        assert!(trades.contains(&(asset1.clone(), -100)));
+        assert!(trades.contains(&(asset2.clone(), 100)));
+
+        // once the lockup ends it trades towards its full target
+        let mut context = get_context(curator);
+        context.block_height(2);
+        context.block_timestamp(1000);
+        testing_env!(context.build());
+        let trades = contract.rebalance();
+        assert_eq!(trades.len(), 3);
+        assert!(trades.contains(&(asset1, -175)));
+        assert!(trades.contains(&(asset2, 25)));
+        assert!(trades.contains(&(locked, 150)));
+    }
+
+    #[test]
+    fn test_set_target_weights_glides() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset1, 5000), (&asset2, 5000)]);
+        set_prices(&mut contract, &[(&asset1, 1), (&asset2, 1)]);
+        set_balances(&mut contract, &[(&asset1, 100), (&asset2, 100)]);
+        contract.rebalance_interval = U64(0);
+
+        let mut context = get_context(curator.clone());
+        context.block_height(1000);
+        testing_env!(context.build());
+        contract.set_target_weights(
+            vec![
+                AssetWeight {
+                    weight: U64(8000),
+                    asset_address: asset1.clone(),
+                },
+                AssetWeight {
+                    weight: U64(2000),
+                    asset_address: asset2.clone(),
+                },
+            ],
+            U64(100),
+        );
+        let weight_of = |contract: &IndexFund, asset_id: &AccountId| {
+            contract
+                .get_effective_weights()
+                .into_iter()
+                .find(|w| w.asset_address == *asset_id)
+                .map(|w| w.weight)
+        };
+        assert_eq!(weight_of(&contract, &asset1), Some(U64(5000)));
+
+        // halfway through, rebalancing targets the midpoint
+        let mut context = get_context(curator.clone());
+        context.block_height(1050);
+        testing_env!(context.build());
+        assert_eq!(weight_of(&contract, &asset1), Some(U64(6500)));
+        assert_eq!(weight_of(&contract, &asset2), Some(U64(3500)));
+        let trades = contract.rebalance();
+        assert!(trades.contains(&(asset1.clone(), 30)));
+        assert!(trades.contains(&(asset2.clone(), -30)));
+
+        let mut context = get_context(curator);
+        context.block_height(1200);
+        testing_env!(context.build());
+        assert_eq!(weight_of(&contract, &asset1), Some(U64(8000)));
+        contract.rebalance();
+        assert!(contract.weight_glide.is_none());
+        assert_eq!(weight_of(&contract, &asset2), Some(U64(2000)));
+    }
+
+    #[test]
+    fn test_batch_update_weights_then_rebalance() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset1, 5000), (&asset2, 5000)]);
+        set_prices(&mut contract, &[(&asset1, 1), (&asset2, 1)]);
+        set_balances(&mut contract, &[(&asset1, 100), (&asset2, 100)]);
+        contract.rebalance_interval = U64(0);
+
+        contract.batch(vec![
+            CuratorAction::UpdateWeights {
+                updates: vec![
+                    AssetWeight {
+                        weight: U64(7500),
+                        asset_address: asset1.clone(),
+                    },
+                    AssetWeight {
+                        weight: U64(2500),
+                        asset_address: asset2.clone(),
+                    },
+                ],
+            },
+            CuratorAction::Rebalance,
+        ]);
+
+        assert_eq!(contract.assets.get(&asset1).unwrap().weight, U64(7500));
+        assert_eq!(contract.get_rebalance_count(), U64(1));
+        let rebalanced = get_logs()
+            .into_iter()
+            .find(|log| log.starts_with("Rebalanced"))
+            .expect("No rebalance log");
+        // the rebalance already targets the new weights
+        assert_eq!(
+            rebalanced,
+            format!(
+                "Rebalanced: {:?}",
+                vec![(asset1, 50i128), (asset2, -50i128)]
+            )
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Can't add assets in a batch while they're validated")]
+    fn test_batch_rejects_validated_new_assets() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset1, 10000)]);
+        contract.set_validate_assets_on_add(true);
+        contract.rebalance_interval = U64(0);
+
+        contract.batch(vec![
+            CuratorAction::UpdateWeights {
+                updates: vec![
+                    AssetWeight {
+                        weight: U64(5000),
+                        asset_address: asset1,
+                    },
+                    AssetWeight {
+                        weight: U64(5000),
+                        asset_address: asset2,
+                    },
+                ],
+            },
+            CuratorAction::Rebalance,
+        ]);
+    }
+
+    #[test]
+    fn test_rebalance_sell_first() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let assets: Vec<AccountId> = ["a.near", "b.near", "c.near", "d.near"]
+            .iter()
+            .map(|id| AccountId::from_str(id).unwrap())
+            .collect();
+
+        let mut contract = fund_with_weights(
+            &curator,
+            &[
+                (&assets[0], 2500),
+                (&assets[1], 2500),
+                (&assets[2], 2500),
+                (&assets[3], 2500),
+            ],
+        );
+        set_prices(
+            &mut contract,
+            &[
+                (&assets[0], 1),
+                (&assets[1], 1),
+                (&assets[2], 1),
+                (&assets[3], 1),
+            ],
+        );
+        // a and c are underweight, b and d overweight
+        set_balances(
+            &mut contract,
+            &[
+                (&assets[0], 50),
+                (&assets[1], 150),
+                (&assets[2], 70),
+                (&assets[3], 130),
+            ],
+        );
+        contract.rebalance_interval = U64(0);
+        contract.set_rebalance_strategy(RebalanceStrategy::SellFirst);
+
+        let trades = contract.rebalance();
+        let signs: Vec<bool> = trades.iter().map(|(_, delta)| *delta > 0).collect();
+        assert_eq!(signs, vec![false, false, true, true]);
+        assert!(trades.contains(&(assets[1].clone(), -50)));
+        assert!(trades.contains(&(assets[3].clone(), -30)));
+        assert!(trades.contains(&(assets[0].clone(), 50)));
+        assert!(trades.contains(&(assets[2].clone(), 30)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Rebalance interval has not elapsed")]
+    fn test_rebalance_before_interval() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset = AccountId::from_str("asset.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset, 10000)]);
+        contract.rebalance_interval = U64(100);
+        contract.last_rebalance = U64(50);
+
+        let mut context = get_context(curator);
+        context.block_height(149);
+        testing_env!(context.build());
+        contract.rebalance();
+    }
+
+    #[test]
+    #[should_panic(expected = "Can't rebalance with zero total weight")]
+    fn test_rebalance_zero_total_weight() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+
+        let mut contract = fund_with_weights(&curator, &[(&asset1, 5000), (&asset2, 5000)]);
+        set_prices(&mut contract, &[(&asset1, 1), (&asset2, 1)]);
+        set_balances(&mut contract, &[(&asset1, 100), (&asset2, 100)]);
+        for asset_id in [&asset1, &asset2] {
+            let mut holding = contract.assets.get(asset_id).unwrap();
+            holding.weight = U64(0);
+            contract.assets.insert(asset_id, &holding);
+        }
+        contract.rebalance_interval = U64(0);
+
+        contract.rebalance();
+    }
+
+    #[test]
+    fn test_storage_usage() {
+        let curator = AccountId::from_str("curator.near").unwrap();
+        let asset1 = AccountId::from_str("asset1.near").unwrap();
+        let asset2 = AccountId::from_str("asset2.near").unwrap();
+
+        let context = get_context(curator.clone());
+        testing_env!(context.build());
+
+        let mut contract = IndexFund::with_defaults();
+        contract.curator_address = Some(curator);
+
+        contract.update_weights(vec![
+            AssetWeight {
+                weight: U64(5000),
+                asset_address: asset1,
+            },
+            AssetWeight {
+                weight: U64(5000),
+                asset_address: asset2,
+            },
+        ]);
+
+        let usage = contract.get_storage_usage();
+        assert!(usage > U64(0));
+
+        let cost = contract.get_storage_cost();
+        assert!(cost > U128(0));
+        assert_eq!(
+            cost,
+            U128(usage.0 as u128 * env::storage_byte_cost().as_yoctonear())
+        );
+    }
+
+    #[test]
+    fn test_storage_deposit_registers_account() {
+        let investor = AccountId::from_str("investor.near").unwrap();
+
+        let mut contract = IndexFund::with_defaults();
+        let min = contract.storage_balance_bounds().min;
+        assert!(min > NearToken::from_near(0));
+        assert!(contract.storage_balance_of(investor.clone()).is_none());
+
+        let mut context = get_context(investor.clone());
+        context.attached_deposit(min);
+        testing_env!(context.build());
+
+        let balance = contract.storage_deposit(None, None);
+        assert_eq!(balance.total, min);
+        assert_eq!(balance.available, NearToken::from_near(0));
+
+        let balance = contract
+            .storage_balance_of(investor.clone())
+            .expect("Investor not registered");
+        assert_eq!(balance.total, min);
+        assert_eq!(contract.shares.get(&investor), Some(U128(0)));
+    }
+
+    #[test]
+    fn test_storage_deposit_twice_is_idempotent() {
+        let investor = AccountId::from_str("investor.near").unwrap();
+
+        let mut contract = IndexFund::with_defaults();
+        let min = contract.storage_balance_bounds().min;
+
+        let mut context = get_context(investor.clone());
+        context.attached_deposit(min);
+        testing_env!(context.build());
+
+        contract.storage_deposit(None, None);
+        contract.internal_mint_shares(&investor, 100);
+
+        // registering again refunds the deposit and leaves the balance untouched
+        let balance = contract.storage_deposit(None, None);
+        assert_eq!(balance.total, min);
+        assert_eq!(contract.shares.get(&investor), Some(U128(100)));
+        assert_eq!(contract.total_shares, U128(100));
+    }
+
+    #[test]
+    fn test_storage_deposit_for_other_account() {
+        let payer = AccountId::from_str("payer.near").unwrap();
+        let investor = AccountId::from_str("investor.near").unwrap();
+
+        let mut contract = IndexFund::with_defaults();
+        let min = contract.storage_balance_bounds().min;
+
+        let mut context = get_context(payer.clone());
+        context.attached_deposit(min);
+        testing_env!(context.build());
+
+        contract.storage_deposit(Some(investor.clone()), None);
+        assert!(contract.storage_balance_of(investor).is_some());
+        assert!(contract.storage_balance_of(payer).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "The fee recipient can't unregister")]
+    fn test_storage_unregister_fee_recipient() {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        let treasury = AccountId::from_str("treasury.near").unwrap();
+
+        testing_env!(get_context(owner.clone()).build());
+        let mut contract = IndexFund::with_defaults();
+        contract.owner_id = Some(owner);
+        contract.set_fee_recipient(treasury.clone());
+
+        let mut context = get_context(treasury);
+        context.attached_deposit(NearToken::from_yoctonear(1));
+        testing_env!(context.build());
+        contract.storage_unregister(Some(true));
+    }
+
+    #[test]
+    fn test_storage_unregister_force_accrues_management_fee() {
+        let owner = AccountId::from_str("owner.near").unwrap();
+        let investor = AccountId::from_str("investor.near").unwrap();
+        let treasury = AccountId::from_str("treasury.near").unwrap();
+
+        testing_env!(get_context(owner.clone()).build());
+        let mut contract = IndexFund::with_defaults();
+        contract.owner_id = Some(owner);
+        contract.shares.insert(&investor, &U128(0));
+        contract.internal_mint_shares(&investor, 1_000_000);
+        contract.set_fee_recipient(treasury.clone());
+        contract.set_management_fee_bps(U64(200));
+
+        // a year's fee on the supply before the burn
+        let mut context = get_context(investor.clone());
+        context.block_timestamp(100 + NS_PER_YEAR);
+        context.attached_deposit(NearToken::from_yoctonear(1));
+        testing_env!(context.build());
+        assert!(contract.storage_unregister(Some(true)));
+        assert_eq!(contract.shares.get(&treasury), Some(U128(20_000)));
+        assert_eq!(contract.total_shares, U128(20_000));
+    }
+
+    #[test]
+    #[should_panic(expected = "The attached deposit is less than the minimum storage balance")]
+    fn test_storage_deposit_insufficient() {
+        let investor = AccountId::from_str("investor.near").unwrap();
+
+        let mut context = get_context(investor);
+        context.attached_deposit(NearToken::from_yoctonear(1));
+        testing_env!(context.build());
+
+        let mut contract = IndexFund::with_defaults();
+        contract.storage_deposit(None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "The account investor.near is not registered")]
+    fn test_mint_shares_to_unregistered_account() {
+        let investor = AccountId::from_str("investor.near").unwrap();
+
+        let mut contract = IndexFund::with_defaults();
+        contract.internal_mint_shares(&investor, 100);
+    }
 }
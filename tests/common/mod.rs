@@ -0,0 +1,118 @@
+// Shared setup for the sandbox tests; each test binary uses a different subset
+#![allow(dead_code)]
+
+use near_sdk::json_types::U128;
+use near_sdk::NearToken;
+use near_workspaces::network::Sandbox;
+use near_workspaces::{Account, Contract, Worker};
+use serde_json::json;
+
+pub type TestResult = Result<(), Box<dyn std::error::Error>>;
+
+/// Deploys and initializes the fund with `curator` registered. The fund account is also its
+/// owner, since `new` makes the caller the owner.
+pub async fn deploy_fund(
+    sandbox: &Worker<Sandbox>,
+    curator: &Account,
+) -> Result<Contract, Box<dyn std::error::Error>> {
+    let wasm = near_workspaces::compile_project("./").await?;
+    let fund = sandbox.dev_deploy(&wasm).await?;
+    fund.call("new")
+        .args_json(json!({ "rebalance_interval": "86400" }))
+        .transact()
+        .await?
+        .into_result()?;
+    curator
+        .call(fund.id(), "register_curator")
+        .args_json(json!({ "curator_address": curator.id() }))
+        .deposit(NearToken::from_near(1))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(fund)
+}
+
+/// Deploys a mock NEP-141 token with `decimals` and registers `accounts` on it
+pub async fn deploy_mock_ft(
+    sandbox: &Worker<Sandbox>,
+    decimals: u8,
+    accounts: &[&near_workspaces::AccountId],
+) -> Result<Contract, Box<dyn std::error::Error>> {
+    let wasm = near_workspaces::compile_project("./tests/contracts/mock-ft").await?;
+    let token = sandbox.dev_deploy(&wasm).await?;
+    token
+        .call("new")
+        .args_json(json!({ "decimals": decimals }))
+        .transact()
+        .await?
+        .into_result()?;
+    for account_id in accounts {
+        token
+            .call("register")
+            .args_json(json!({ "account_id": account_id }))
+            .transact()
+            .await?
+            .into_result()?;
+    }
+    Ok(token)
+}
+
+pub async fn mint(
+    token: &Contract,
+    account_id: &near_workspaces::AccountId,
+    amount: u128,
+) -> TestResult {
+    token
+        .call("mint")
+        .args_json(json!({ "account_id": account_id, "amount": U128(amount) }))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+pub async fn ft_balance_of(
+    token: &Contract,
+    account_id: &near_workspaces::AccountId,
+) -> Result<u128, Box<dyn std::error::Error>> {
+    let balance: U128 = token
+        .view("ft_balance_of")
+        .args_json(json!({ "account_id": account_id }))
+        .await?
+        .json()?;
+    Ok(balance.0)
+}
+
+/// Registers `account` for fund shares (NEP-145)
+pub async fn register_shareholder(fund: &Contract, account: &Account) -> TestResult {
+    account
+        .call(fund.id(), "storage_deposit")
+        .args_json(json!({}))
+        .deposit(NearToken::from_millinear(100))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+/// Deposits `amount` of `token` into the fund for `account`
+pub async fn deposit_token(
+    fund: &Contract,
+    token: &Contract,
+    account: &Account,
+    amount: u128,
+) -> TestResult {
+    account
+        .call(token.id(), "ft_transfer_call")
+        .args_json(json!({
+            "receiver_id": fund.id(),
+            "amount": U128(amount),
+            "msg": "",
+        }))
+        .deposit(NearToken::from_yoctonear(1))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
@@ -0,0 +1,58 @@
+// Swap stand-in for the sandbox tests. Tokens sent with `ft_transfer_call` and a
+// `{"token_out": ...}` msg are swapped at a fixed `rate` and the proceeds sent back to the sender
+// as an `ft_transfer_call` with the "liquidation" msg, before the incoming transfer resolves.
+// The DEX must hold enough of `token_out`.
+use near_contract_standards::fungible_token::core::ext_ft_core;
+use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+use near_sdk::json_types::U128;
+use near_sdk::serde::Deserialize;
+use near_sdk::{env, near, AccountId, NearToken, PanicOnDefault, PromiseOrValue};
+
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct SwapMsg {
+    token_out: AccountId,
+}
+
+#[near(contract_state)]
+#[derive(PanicOnDefault)]
+pub struct MockDex {
+    // units of `token_out` paid per unit received
+    rate: u128,
+}
+
+#[near]
+impl MockDex {
+    #[init]
+    pub fn new(rate: U128) -> Self {
+        Self { rate: rate.0 }
+    }
+
+    // The whole incoming amount is used once the proceeds have been handed over
+    #[private]
+    pub fn on_proceeds_sent(&mut self) -> U128 {
+        U128(0)
+    }
+}
+
+#[near]
+impl FungibleTokenReceiver for MockDex {
+    fn ft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        amount: U128,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        let swap: SwapMsg = near_sdk::serde_json::from_str(&msg).expect("Invalid swap msg");
+        ext_ft_core::ext(swap.token_out)
+            .with_attached_deposit(NearToken::from_yoctonear(1))
+            .ft_transfer_call(
+                sender_id,
+                U128(amount.0 * self.rate),
+                None,
+                "liquidation".to_string(),
+            )
+            .then(Self::ext(env::current_account_id()).on_proceeds_sent())
+            .into()
+    }
+}
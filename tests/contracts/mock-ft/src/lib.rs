@@ -0,0 +1,111 @@
+// NEP-141 token for the sandbox tests. Anyone can register accounts and mint, and `near_deposit`
+// mints the attached NEAR like wrap.near does, so the same contract doubles as wrapped NEAR.
+use near_contract_standards::fungible_token::core::FungibleTokenCore;
+use near_contract_standards::fungible_token::metadata::{
+    FungibleTokenMetadata, FungibleTokenMetadataProvider, FT_METADATA_SPEC,
+};
+use near_contract_standards::fungible_token::resolver::FungibleTokenResolver;
+use near_contract_standards::fungible_token::FungibleToken;
+use near_sdk::json_types::U128;
+use near_sdk::{env, near, require, AccountId, PanicOnDefault, PromiseOrValue};
+
+#[near(contract_state)]
+#[derive(PanicOnDefault)]
+pub struct MockFt {
+    token: FungibleToken,
+    decimals: u8,
+    // makes `near_deposit` fail, to exercise the fund's refund path
+    fail_wraps: bool,
+}
+
+#[near]
+impl MockFt {
+    #[init]
+    pub fn new(decimals: u8) -> Self {
+        Self {
+            token: FungibleToken::new(b"t"),
+            decimals,
+            fail_wraps: false,
+        }
+    }
+
+    pub fn register(&mut self, account_id: AccountId) {
+        if !self.token.accounts.contains_key(&account_id) {
+            self.token.internal_register_account(&account_id);
+        }
+    }
+
+    pub fn mint(&mut self, account_id: AccountId, amount: U128) {
+        self.register(account_id.clone());
+        self.token.internal_deposit(&account_id, amount.0);
+    }
+
+    pub fn set_fail_wraps(&mut self, fail_wraps: bool) {
+        self.fail_wraps = fail_wraps;
+    }
+
+    #[payable]
+    pub fn near_deposit(&mut self) {
+        require!(!self.fail_wraps, "Wrapping is disabled");
+        let account_id = env::predecessor_account_id();
+        self.mint(account_id, U128(env::attached_deposit().as_yoctonear()));
+    }
+}
+
+#[near]
+impl FungibleTokenCore for MockFt {
+    #[payable]
+    fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>) {
+        self.token.ft_transfer(receiver_id, amount, memo)
+    }
+
+    #[payable]
+    fn ft_transfer_call(
+        &mut self,
+        receiver_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        self.token.ft_transfer_call(receiver_id, amount, memo, msg)
+    }
+
+    fn ft_total_supply(&self) -> U128 {
+        self.token.ft_total_supply()
+    }
+
+    fn ft_balance_of(&self, account_id: AccountId) -> U128 {
+        self.token.ft_balance_of(account_id)
+    }
+}
+
+#[near]
+impl FungibleTokenResolver for MockFt {
+    #[private]
+    fn ft_resolve_transfer(
+        &mut self,
+        sender_id: AccountId,
+        receiver_id: AccountId,
+        amount: U128,
+    ) -> U128 {
+        let (used_amount, _) =
+            self.token
+                .internal_ft_resolve_transfer(&sender_id, receiver_id, amount);
+        used_amount.into()
+    }
+}
+
+#[near]
+impl FungibleTokenMetadataProvider for MockFt {
+    fn ft_metadata(&self) -> FungibleTokenMetadata {
+        FungibleTokenMetadata {
+            spec: FT_METADATA_SPEC.to_string(),
+            name: "Mock Token".to_string(),
+            symbol: "MOCK".to_string(),
+            icon: None,
+            reference: None,
+            reference_hash: None,
+            decimals: self.decimals,
+        }
+    }
+}
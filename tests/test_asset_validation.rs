@@ -0,0 +1,60 @@
+use serde_json::json;
+
+mod common;
+use common::*;
+
+async fn weighted_assets(
+    fund: &near_workspaces::Contract,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let weights: Vec<serde_json::Value> = fund.view("get_weights").await?.json()?;
+    let mut assets: Vec<String> = weights
+        .iter()
+        .map(|w| w["asset_address"].as_str().unwrap().to_string())
+        .collect();
+    assets.sort();
+    Ok(assets)
+}
+
+#[tokio::test]
+async fn test_validate_assets_on_add() -> TestResult {
+    let sandbox = near_workspaces::sandbox().await?;
+    let curator = sandbox.dev_create_account().await?;
+    let fund = deploy_fund(&sandbox, &curator).await?;
+    let token = deploy_mock_ft(&sandbox, 6, &[]).await?;
+    // an account without a contract, so `ft_metadata` fails
+    let not_a_token = sandbox.dev_create_account().await?;
+
+    curator
+        .call(fund.id(), "set_validate_assets_on_add")
+        .args_json(json!({ "enabled": true }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    // a real token is added once its metadata call succeeds
+    curator
+        .call(fund.id(), "update_weights")
+        .args_json(json!({ "updates": [{ "asset_address": token.id(), "weight": "10000" }] }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+    assert_eq!(weighted_assets(&fund).await?, vec![token.id().to_string()]);
+
+    // an update adding an invalid asset is dropped as a whole
+    curator
+        .call(fund.id(), "update_weights")
+        .args_json(json!({ "updates": [
+            { "asset_address": token.id(), "weight": "5000" },
+            { "asset_address": not_a_token.id(), "weight": "5000" },
+        ]}))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+    assert_eq!(weighted_assets(&fund).await?, vec![token.id().to_string()]);
+    let weights: Vec<serde_json::Value> = fund.view("get_weights").await?.json()?;
+    assert_eq!(weights[0]["weight"], "10000");
+
+    Ok(())
+}
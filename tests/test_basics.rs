@@ -1,4 +1,4 @@
-use near_sdk::json_types::{U128, U64};
+use near_sdk::json_types::U64;
 use serde_json::json;
 
 #[tokio::test]
@@ -0,0 +1,91 @@
+use near_sdk::json_types::U128;
+use serde_json::json;
+
+mod common;
+use common::*;
+
+#[tokio::test]
+async fn test_remove_asset_into_through_dex() -> TestResult {
+    let sandbox = near_workspaces::sandbox().await?;
+    let curator = sandbox.dev_create_account().await?;
+    let investor = sandbox.dev_create_account().await?;
+    let fund = deploy_fund(&sandbox, &curator).await?;
+
+    // the DEX pays 2 usdc per old token
+    let dex_wasm = near_workspaces::compile_project("./tests/contracts/mock-dex").await?;
+    let dex = sandbox.dev_deploy(&dex_wasm).await?;
+    dex.call("new")
+        .args_json(json!({ "rate": U128(2) }))
+        .transact()
+        .await?
+        .into_result()?;
+    let accounts = [fund.id(), investor.id(), dex.id()];
+    let old = deploy_mock_ft(&sandbox, 0, &accounts).await?;
+    let usdc = deploy_mock_ft(&sandbox, 0, &accounts).await?;
+    mint(&old, investor.id(), 500).await?;
+    mint(&usdc, investor.id(), 500).await?;
+    mint(&usdc, dex.id(), 10_000).await?;
+
+    curator
+        .call(fund.id(), "update_weights")
+        .args_json(json!({ "updates": [
+            { "asset_address": old.id(), "weight": "5000" },
+            { "asset_address": usdc.id(), "weight": "5000" },
+        ]}))
+        .transact()
+        .await?
+        .into_result()?;
+    curator
+        .call(fund.id(), "set_oracle")
+        .args_json(json!({ "oracle_address": curator.id() }))
+        .transact()
+        .await?
+        .into_result()?;
+    curator
+        .call(fund.id(), "update_prices")
+        .args_json(json!({ "prices": [[old.id(), "1"], [usdc.id(), "1"]] }))
+        .transact()
+        .await?
+        .into_result()?;
+    register_shareholder(&fund, &investor).await?;
+    deposit_token(&fund, &old, &investor, 500).await?;
+    deposit_token(&fund, &usdc, &investor, 500).await?;
+
+    curator
+        .call(fund.id(), "update_weights")
+        .args_json(json!({ "updates": [
+            { "asset_address": old.id(), "weight": "0" },
+            { "asset_address": usdc.id(), "weight": "10000" },
+        ]}))
+        .transact()
+        .await?
+        .into_result()?;
+    curator
+        .call(fund.id(), "set_dex")
+        .args_json(json!({ "dex_id": dex.id() }))
+        .transact()
+        .await?
+        .into_result()?;
+    curator
+        .call(fund.id(), "remove_asset_into")
+        .args_json(json!({ "asset_id": old.id(), "liquidate_into": usdc.id() }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+
+    let assets: Vec<String> = fund.view("get_assets").await?.json()?;
+    assert_eq!(assets, vec![usdc.id().to_string()]);
+    let nav: U128 = fund.view("get_nav").await?.json()?;
+    assert_eq!(nav, U128(1500));
+    assert_eq!(ft_balance_of(&usdc, fund.id()).await?, 1500);
+    assert_eq!(ft_balance_of(&old, dex.id()).await?, 500);
+    let liquidation: Option<serde_json::Value> = fund.view("get_liquidation").await?.json()?;
+    assert_eq!(liquidation, None);
+
+    // deposits are open again once the swap has resolved
+    deposit_token(&fund, &usdc, &investor, 100).await?;
+    assert_eq!(ft_balance_of(&usdc, fund.id()).await?, 1600);
+
+    Ok(())
+}
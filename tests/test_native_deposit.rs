@@ -0,0 +1,88 @@
+use near_sdk::json_types::U128;
+use near_sdk::NearToken;
+use serde_json::json;
+
+mod common;
+use common::*;
+
+#[tokio::test]
+async fn test_deposit_native_near() -> TestResult {
+    let sandbox = near_workspaces::sandbox().await?;
+    let curator = sandbox.dev_create_account().await?;
+    let investor = sandbox.dev_create_account().await?;
+    let fund = deploy_fund(&sandbox, &curator).await?;
+    let wnear = deploy_mock_ft(&sandbox, 24, &[fund.id(), investor.id()]).await?;
+
+    curator
+        .call(fund.id(), "update_weights")
+        .args_json(json!({ "updates": [{ "asset_address": wnear.id(), "weight": "10000" }] }))
+        .transact()
+        .await?
+        .into_result()?;
+    curator
+        .call(fund.id(), "set_oracle")
+        .args_json(json!({ "oracle_address": curator.id() }))
+        .transact()
+        .await?
+        .into_result()?;
+    // $5 in a 6-decimal quote asset
+    curator
+        .call(fund.id(), "update_prices")
+        .args_json(json!({ "prices": [[wnear.id(), "5000000"]] }))
+        .transact()
+        .await?
+        .into_result()?;
+    curator
+        .call(fund.id(), "set_wrap_near")
+        .args_json(json!({ "wrap_near_id": wnear.id() }))
+        .transact()
+        .await?
+        .into_result()?;
+    register_shareholder(&fund, &investor).await?;
+
+    let minted: U128 = investor
+        .call(fund.id(), "deposit")
+        .deposit(NearToken::from_near(1))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?
+        .json()?;
+    assert_eq!(minted, U128(5_000_000));
+    assert_eq!(
+        ft_balance_of(&wnear, fund.id()).await?,
+        NearToken::from_near(1).as_yoctonear()
+    );
+    let shares: U128 = fund
+        .view("ft_balance_of")
+        .args_json(json!({ "account_id": investor.id() }))
+        .await?
+        .json()?;
+    assert_eq!(shares, minted);
+
+    // a failed wrap sends the NEAR back and mints nothing
+    wnear
+        .call("set_fail_wraps")
+        .args_json(json!({ "fail_wraps": true }))
+        .transact()
+        .await?
+        .into_result()?;
+    let before = investor.view_account().await?.balance;
+    let minted: U128 = investor
+        .call(fund.id(), "deposit")
+        .deposit(NearToken::from_near(1))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?
+        .json()?;
+    assert_eq!(minted, U128(0));
+    let spent = before.saturating_sub(investor.view_account().await?.balance);
+    assert!(spent < NearToken::from_millinear(100), "spent {}", spent);
+    assert_eq!(
+        ft_balance_of(&wnear, fund.id()).await?,
+        NearToken::from_near(1).as_yoctonear()
+    );
+
+    Ok(())
+}
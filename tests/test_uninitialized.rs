@@ -0,0 +1,47 @@
+use serde_json::json;
+
+mod common;
+use common::*;
+
+// Deployed but never `new`-initialized, so every call runs on `Default`
+async fn deploy_uninitialized(
+    sandbox: &near_workspaces::Worker<near_workspaces::network::Sandbox>,
+) -> Result<near_workspaces::Contract, Box<dyn std::error::Error>> {
+    let wasm = near_workspaces::compile_project("./").await?;
+    Ok(sandbox.dev_deploy(&wasm).await?)
+}
+
+#[tokio::test]
+async fn test_mutating_calls_rejected_uninitialized() -> TestResult {
+    let sandbox = near_workspaces::sandbox().await?;
+    let fund = deploy_uninitialized(&sandbox).await?;
+    let user = sandbox.dev_create_account().await?;
+
+    let calls = [
+        (
+            "update_weights",
+            json!({ "updates": [{ "asset_address": user.id(), "weight": "10000" }] }),
+        ),
+        (
+            "ft_on_transfer",
+            json!({ "sender_id": user.id(), "amount": "100", "msg": "" }),
+        ),
+        ("rebalance", json!({})),
+    ];
+    for (method, args) in calls {
+        let outcome = user
+            .call(fund.id(), method)
+            .args_json(args)
+            .max_gas()
+            .transact()
+            .await?;
+        let failure = outcome.into_result().expect_err(method);
+        assert!(
+            format!("{:?}", failure).contains("The contract is not initialized"),
+            "{} failed with {:?}",
+            method,
+            failure
+        );
+    }
+    Ok(())
+}